@@ -0,0 +1,182 @@
+use vxl_iset::instruction::Instruction;
+use vxl_iset::instruction_arguments::Register;
+
+/// The mnemonics understood by the assembler, used to map opcodes back to their names.
+///
+/// Every instruction of the set must be named here, which the tests check against each opcode
+/// the set defines.
+const MNEMONICS: &[&str] = &[
+    "ldb", "ldi", "ldf", "mov", "malloc", "free", "setb", "isetb", "getb", "length", "addu",
+    "mulu", "and", "xor", "cmp", "jmp", "jge", "call", "ret", "halt", "syscall",
+];
+
+/// Returns the mnemonic that assembles to the given opcode, the inverse of
+/// `Instruction::from_string`.
+pub fn mnemonic_for_opcode(code: u8) -> Option<&'static str> {
+    return MNEMONICS
+        .iter()
+        .copied()
+        .find(|name| Instruction::from_string(name) == Some(code));
+}
+
+/// Every register of the instruction set, in encoding order.
+pub const REGISTERS: [Register; 16] = [
+    Register::RSP,
+    Register::RFP,
+    Register::ROU,
+    Register::RFL,
+    Register::RRA,
+    Register::RRB,
+    Register::R0,
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::R8,
+    Register::R9,
+];
+
+/// Returns the canonical spelling of a register, without the leading `$`.
+pub const fn register_name(register: Register) -> &'static str {
+    // No wildcard, so a register added to the instruction set fails to compile here.
+    return match register {
+        Register::RSP => "rsp",
+        Register::RFP => "rfp",
+        Register::ROU => "rou",
+        Register::RFL => "rfl",
+        Register::RRA => "rra",
+        Register::RRB => "rrb",
+        Register::R0 => "r0",
+        Register::R1 => "r1",
+        Register::R2 => "r2",
+        Register::R3 => "r3",
+        Register::R4 => "r4",
+        Register::R5 => "r5",
+        Register::R6 => "r6",
+        Register::R7 => "r7",
+        Register::R8 => "r8",
+        Register::R9 => "r9",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    use crate::processing::Lexer;
+    use crate::token::TokenType;
+
+    use super::*;
+
+    #[test]
+    fn test_mnemonic_round_trip() {
+        for name in MNEMONICS {
+            if let Some(code) = Instruction::from_string(name) {
+                assert_eq!(mnemonic_for_opcode(code), Some(*name));
+            }
+        }
+
+        // Every opcode the instruction set defines must be reachable from a mnemonic, so that
+        // an instruction missing from MNEMONICS is caught here.
+        for code in 0..=u8::MAX {
+            if Instruction::register_count(code).is_none() {
+                assert_eq!(mnemonic_for_opcode(code), None);
+                continue;
+            }
+
+            match mnemonic_for_opcode(code) {
+                Some(name) => assert_eq!(Instruction::from_string(name), Some(code)),
+                None => panic!("Opcode {:#04x} has no mnemonic", code),
+            }
+        }
+
+        assert_eq!(mnemonic_for_opcode(0x3), Some("ldi"));
+        assert_eq!(mnemonic_for_opcode(0x45), Some("halt"));
+    }
+
+    #[test]
+    fn test_opcode_tokens_round_trip() {
+        let tokens = Lexer::tokenize_string("ldi $r0, 5\njmp 0\nhalt".to_string()).unwrap();
+
+        for token in tokens {
+            if let TokenType::Opcode(code) = token.token_type() {
+                assert_eq!(
+                    mnemonic_for_opcode(code),
+                    Some(token.source_text().as_str())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_registers_lex() {
+        for (i, register) in REGISTERS.iter().enumerate() {
+            assert_eq!(*register as u8 as usize, i);
+
+            let tokens = Lexer::tokenize_string(format!("${}", register_name(*register))).unwrap();
+
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(tokens[0].token_type(), TokenType::Register(*register));
+        }
+    }
+
+    #[test]
+    fn test_lexed_registers_are_known() {
+        let alphabet: Vec<char> = ('a'..='z').chain('0'..='9').collect();
+        let mut spellings = Vec::new();
+
+        for a in &alphabet {
+            spellings.push(String::from(*a));
+
+            for b in &alphabet {
+                spellings.push(format!("{}{}", a, b));
+
+                for c in &alphabet {
+                    spellings.push(format!("{}{}{}", a, b, c));
+                }
+            }
+        }
+
+        for spelling in spellings {
+            let register = match Lexer::tokenize_string(format!("${}", spelling)) {
+                Ok(tokens) => match tokens[..] {
+                    [ref token] => match token.token_type() {
+                        TokenType::Register(r) => r,
+                        _ => continue,
+                    },
+                    _ => continue,
+                },
+                Err(_) => continue,
+            };
+
+            // General purpose registers may be written with leading zeros, as in `$r07`.
+            let name = match spelling.strip_prefix('r') {
+                Some(digits) if digits.chars().all(|c| c.is_ascii_digit()) => {
+                    let digits = digits.trim_start_matches('0');
+
+                    format!("r{}", if digits.is_empty() { "0" } else { digits })
+                }
+                _ => spelling,
+            };
+
+            let expected = match name.as_str() {
+                "sp" => Register::RSP,
+                "fp" => Register::RFP,
+                name => match REGISTERS.iter().find(|r| register_name(**r) == name) {
+                    Some(r) => *r,
+                    None => panic!(
+                        "${} lexes as {:?} but is not a known spelling",
+                        name, register
+                    ),
+                },
+            };
+
+            assert_eq!(register, expected);
+        }
+    }
+}
@@ -1,7 +1,11 @@
-use crate::text_mapping::{FilePtr, Position, Source, TextRange};
+use crate::processing::{MAX_HEX_DIGITS, MAX_IMAGE_SIZE};
+use crate::text_mapping::{FileInfo, FilePtr, Position, Source, TextRange};
 use crate::token::Token;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use core::fmt;
+use core::fmt::Write;
 use either::Either;
 
 pub trait VXASMError: fmt::Display + fmt::Debug {
@@ -13,6 +17,7 @@ pub trait VXASMError: fmt::Display + fmt::Debug {
 #[derive(Clone, Debug, PartialEq)]
 pub enum LexerError {
     UnexpectedCharacter(char, Position, Source),
+    NonAsciiIdentifier(char, Position, Source),
     EmptyIdentifier(Position, Source),
     InvalidHexLiteral(TextRange),
     InvalidBinaryLiteral(TextRange),
@@ -21,9 +26,17 @@ pub enum LexerError {
     InvalidUnsignedIntegerLiteral(TextRange),
     InvalidSignedIntegerLiteral(TextRange),
     InvalidRegister(TextRange),
+    InvalidRegisterRange(TextRange),
     ExpectedRegisterFoundEOF(Position, Source),
     UnknownDirective(TextRange),
+    UnknownDirectiveWithSuggestion(TextRange, &'static str),
     UnterminatedString(TextRange),
+    InvalidEscape(TextRange),
+    InvalidCharLiteral(TextRange),
+    UnterminatedBlockComment(Position, Source),
+    IdentifierTooLong(TextRange),
+    LineTooLong(Position, Source),
+    UnknownRadixPrefix(TextRange),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -39,9 +52,11 @@ pub enum PreProcessorError {
     ExpectedStringFound(Token, Token),
     InvalidConstantValue(Token, Token),
     UndefinedLabel(Token),
+    DuplicateLabel(Token, Token),
     UnexpectedEndif(Token),
     UnexpectedEndRepeat(Token),
     UnexpectedElse(Token),
+    UnexpectedElif(Token),
     UnterminatedRepeat(Token),
     ForbiddenDirective(Token),
     ExpectedIdentifierFlagFound(Token, Token),
@@ -50,6 +65,43 @@ pub enum PreProcessorError {
     ExpectedIdentifierFoundEOF(Token),
     InvalidConstantName(Token),
     ImportNotPermitted(Token),
+    InvalidMacroName(Token),
+    UnexpectedEndMacro(Token),
+    UnterminatedMacro(Token),
+    ExpectedMacroArgumentFoundEOF(Token),
+    ExpectedCommaFound(Token, Token),
+    MacroRecursionLimit(Token),
+    UserError(TextRange, Rc<str>),
+    ExpectedIdentifierFound(Token, Token),
+    DuplicateEntry(Token),
+    InvalidConstantExpression(TextRange),
+    DivisionByZero(TextRange),
+    UndefinedConstant(Token, TextRange),
+    FillValueOutOfRange(Token, Token),
+    OrgMovesBackward(Token, usize),
+    ImageTooLarge(Token, usize),
+    StringConstantAsNumber(Token),
+    NumericConstantAsString(Token, Token),
+}
+
+/// Problems found by the lexer that do not stop assembly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LexerWarning {
+    /// A line whose leading whitespace contains both tabs and spaces, at the start of the line.
+    MixedIndentation(Position, Source),
+}
+
+/// Problems found by the preprocessor that do not stop assembly.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreProcessorWarning {
+    ShadowedConstant(Token),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    Lexer(LexerError),
+    FileNotFound(Token),
+    ImportCycle(Vec<String>, Token),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -62,10 +114,119 @@ pub enum ParserError {
     ExpectedUnsignedIntegerForOpcodeArgument(Token, usize),
     ExpectedImmediateForOpcodeArgument(Token, usize),
     ExpectedCommaAfter(Token),
+    UnbalancedParenthesis(TextRange),
+    InvalidOperandExpression(TextRange),
+    DivisionByZero(TextRange),
+}
+
+/// The kinds of operand an instruction takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    Immediate,
+    Address,
+}
+
+/// Errors from assembling a file straight to machine code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssembleError {
+    PreProcessor(PreProcessorError),
+    /// A label or constant that is referenced but never defined.
+    UndefinedSymbol(Token),
+    Parser(ParserError),
+    /// An instruction given the wrong number of operands, spanning the instruction.
+    OperandCountMismatch {
+        expected: usize,
+        found: usize,
+        span: TextRange,
+    },
+    /// An operand of the wrong kind, such as a register where an immediate is expected.
+    OperandKindMismatch {
+        expected: OperandKind,
+        index: usize,
+        span: TextRange,
+    },
+    /// Tokens lexed from a string rather than a file, which cannot be preprocessed.
+    NotAFile(Token),
+}
+
+impl LexerError {
+    /// The starting and ending positions this error refers to.
+    ///
+    /// Errors reported at a single position start and end at the same place.
+    pub fn span(&self) -> (Position, Position) {
+        return match self {
+            LexerError::UnexpectedCharacter(_, pos, _)
+            | LexerError::NonAsciiIdentifier(_, pos, _)
+            | LexerError::EmptyIdentifier(pos, _)
+            | LexerError::UnexpectedSecondDecimalPoint(pos, _)
+            | LexerError::ExpectedRegisterFoundEOF(pos, _)
+            | LexerError::UnterminatedBlockComment(pos, _)
+            | LexerError::LineTooLong(pos, _) => (*pos, *pos),
+            LexerError::InvalidHexLiteral(range)
+            | LexerError::InvalidBinaryLiteral(range)
+            | LexerError::InvalidFloatLiteral(range)
+            | LexerError::InvalidUnsignedIntegerLiteral(range)
+            | LexerError::InvalidSignedIntegerLiteral(range)
+            | LexerError::InvalidRegister(range)
+            | LexerError::InvalidRegisterRange(range)
+            | LexerError::UnknownDirective(range)
+            | LexerError::UnknownDirectiveWithSuggestion(range, _)
+            | LexerError::UnterminatedString(range)
+            | LexerError::InvalidEscape(range)
+            | LexerError::InvalidCharLiteral(range)
+            | LexerError::IdentifierTooLong(range)
+            | LexerError::UnknownRadixPrefix(range) => (range.start(), range.end()),
+        };
+    }
+}
+
+/// Renders a lexer error with the offending source line and a caret underneath it.
+///
+/// Ranges spanning several lines are only underlined on their first line. The caret is placed by
+/// character rather than by column, keeping any tabs before it, so that it lines up with the
+/// echoed line whatever the tab width.
+pub fn render_diagnostic(err: &LexerError, file: &FileInfo) -> String {
+    let (start, end) = err.span();
+    let (line, col) = start.display_line_col();
+
+    let source_line = file.line_text(start.row()).unwrap_or("");
+    let offset = match file.line_start(start.row()) {
+        Some(line_start) => start.index().saturating_sub(line_start.index()),
+        None => 0,
+    };
+
+    let width = if end.row() == start.row() && end.index() > start.index() {
+        end.index() - start.index()
+    } else if end.row() != start.row() {
+        source_line.chars().count().saturating_sub(offset).max(1)
+    } else {
+        1
+    };
+
+    let padding: String = source_line
+        .chars()
+        .chain(core::iter::repeat(' '))
+        .take(offset)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let gutter = line.to_string().len();
+    let mut output = String::new();
+
+    writeln!(output, "error: {}", err).unwrap();
+    writeln!(output, "{:gutter$}--> {}:{}:{}", "", file.name(), line, col).unwrap();
+    writeln!(output, "{:gutter$} |", "").unwrap();
+    writeln!(output, "{} | {}", line, source_line).unwrap();
+    write!(output, "{:gutter$} | {}{}", "", padding, "^".repeat(width)).unwrap();
+
+    return output;
 }
 
 impl VXASMError for LexerError {}
 
+impl core::error::Error for LexerError {}
+
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         return match self {
@@ -91,6 +252,27 @@ impl fmt::Display for LexerError {
                 }
             },
             #[cfg(not(feature = "show-source_string"))]
+            LexerError::NonAsciiIdentifier(ch, pos, source) => {
+                write!(
+                    f,
+                    "Identifiers may only contain ASCII characters, found \'{}\' in {} at {}.",
+                    ch, source, pos
+                )
+            }
+            #[cfg(feature = "show-source_string")]
+            LexerError::NonAsciiIdentifier(ch, pos, source) => match source {
+                Either::Left(fl) => write!(
+                    f,
+                    "Identifiers may only contain ASCII characters, found \'{}\' in {} at {}.",
+                    ch, fl, pos
+                ),
+                Either::Right(s) => write!(
+                    f,
+                    "Identifiers may only contain ASCII characters, found \'{}\' at {}, in source string:\n{}",
+                    ch, pos, s
+                ),
+            },
+            #[cfg(not(feature = "show-source_string"))]
             LexerError::EmptyIdentifier(pos, file) => {
                 write!(f, "Empty identifier in {} at {}.", file, pos)
             }
@@ -101,7 +283,13 @@ impl fmt::Display for LexerError {
                     write!(f, "Empty identifier in at {} in source string:\n{}", pos, s)
                 }
             },
-            LexerError::InvalidHexLiteral(range) => write!(f, "Invalid hex literal. {}", range),
+            LexerError::InvalidHexLiteral(range) => {
+                if range.len() > MAX_HEX_DIGITS {
+                    write!(f, "Invalid hex literal, the value exceeds 64 bits. {}", range)
+                } else {
+                    write!(f, "Invalid hex literal. {}", range)
+                }
+            }
             LexerError::InvalidBinaryLiteral(range) => {
                 write!(f, "Invalid binary literal. {}", range)
             }
@@ -134,6 +322,9 @@ impl fmt::Display for LexerError {
                 write!(f, "Invalid signed integer literal. {}", range)
             }
             LexerError::InvalidRegister(range) => write!(f, "Invalid register. {}", range),
+            LexerError::InvalidRegisterRange(range) => {
+                write!(f, "Invalid register range. {}", range)
+            }
             #[cfg(not(feature = "show-source_string"))]
             LexerError::ExpectedRegisterFoundEOF(pos, source) => {
                 write!(
@@ -156,7 +347,56 @@ impl fmt::Display for LexerError {
                 }
             },
             LexerError::UnknownDirective(range) => write!(f, "Unknown directive. {}", range),
+            LexerError::UnknownDirectiveWithSuggestion(range, suggestion) => write!(
+                f,
+                "Unknown directive, did you mean %{}? {}",
+                suggestion, range
+            ),
             LexerError::UnterminatedString(range) => write!(f, "Unterminated string. {}", range),
+            LexerError::InvalidEscape(range) => write!(f, "Invalid escape sequence. {}", range),
+            LexerError::InvalidCharLiteral(range) => {
+                write!(f, "Invalid character literal. {}", range)
+            }
+            LexerError::IdentifierTooLong(range) => {
+                write!(f, "Identifier exceeds the maximum length. {}", range)
+            }
+            LexerError::UnknownRadixPrefix(range) => {
+                write!(f, "Unknown radix prefix. {}", range)
+            }
+            #[cfg(not(feature = "show-source_string"))]
+            LexerError::UnterminatedBlockComment(pos, source) => {
+                write!(f, "Unterminated block comment in {} at {}", source, pos)
+            }
+            #[cfg(feature = "show-source_string")]
+            LexerError::UnterminatedBlockComment(pos, source) => match source {
+                Either::Left(file) => {
+                    write!(f, "Unterminated block comment in {} at {}", file, pos)
+                }
+                Either::Right(s) => {
+                    write!(
+                        f,
+                        "Unterminated block comment at {} in source string:\n{}",
+                        pos, s
+                    )
+                }
+            },
+            #[cfg(not(feature = "show-source_string"))]
+            LexerError::LineTooLong(pos, source) => {
+                write!(f, "Line exceeds the maximum length in {} at {}", source, pos)
+            }
+            #[cfg(feature = "show-source_string")]
+            LexerError::LineTooLong(pos, source) => match source {
+                Either::Left(file) => {
+                    write!(f, "Line exceeds the maximum length in {} at {}", file, pos)
+                }
+                Either::Right(s) => {
+                    write!(
+                        f,
+                        "Line exceeds the maximum length at {} in source string:\n{}",
+                        pos, s
+                    )
+                }
+            },
         };
     }
 }
@@ -213,9 +453,17 @@ impl fmt::Display for PreProcessorError {
                 found.lexeme()
             ),
             PreProcessorError::UndefinedLabel(lbl) => write!(f, "Undefined label {}", lbl.lexeme()),
+            PreProcessorError::DuplicateLabel(label, previous) => write!(
+                f,
+                "Duplicate label {}, previously defined at {}",
+                label.lexeme(),
+                previous.lexeme()
+            ),
             PreProcessorError::UnexpectedElse(reference)
+            | PreProcessorError::UnexpectedElif(reference)
             | PreProcessorError::UnexpectedEndif(reference)
-            | PreProcessorError::UnexpectedEndRepeat(reference) => {
+            | PreProcessorError::UnexpectedEndRepeat(reference)
+            | PreProcessorError::UnexpectedEndMacro(reference) => {
                 write!(f, "Unexpected token. {}", reference.lexeme())
             }
             PreProcessorError::UnterminatedRepeat(reference) => {
@@ -234,7 +482,7 @@ impl fmt::Display for PreProcessorError {
             ),
             PreProcessorError::UnterminatedIf(reference) => write!(
                 f,
-                "Expected 'end_if' or 'else' following 'if'. {}",
+                "Expected 'end_if', 'elif' or 'else' following 'if'. {}",
                 reference.lexeme()
             ),
             PreProcessorError::UnterminatedElse(reference) => write!(
@@ -255,6 +503,131 @@ impl fmt::Display for PreProcessorError {
                 "Import statements are not permitted from strings. {}",
                 reference.lexeme()
             ),
+            PreProcessorError::InvalidMacroName(name) => {
+                write!(f, "Invalid macro name. {}", name.lexeme())
+            }
+            PreProcessorError::UnterminatedMacro(reference) => write!(
+                f,
+                "Expected 'end_macro' following 'macro'. {}",
+                reference.lexeme()
+            ),
+            PreProcessorError::ExpectedMacroArgumentFoundEOF(reference) => write!(
+                f,
+                "Expected a macro argument but found EOF. {}",
+                reference.lexeme()
+            ),
+            PreProcessorError::ExpectedCommaFound(reference, found) => write!(
+                f,
+                "Expected ',' following {} but found {}",
+                reference.lexeme(),
+                found.lexeme()
+            ),
+            PreProcessorError::MacroRecursionLimit(reference) => write!(
+                f,
+                "Macro expansion exceeded the maximum depth. {}",
+                reference.lexeme()
+            ),
+            PreProcessorError::UserError(range, message) => write!(f, "{} {}", message, range),
+            PreProcessorError::ExpectedIdentifierFound(reference, found) => write!(
+                f,
+                "Expected an identifier following {} but found {}",
+                reference.lexeme(),
+                found.lexeme()
+            ),
+            PreProcessorError::DuplicateEntry(reference) => {
+                write!(
+                    f,
+                    "The entry point has already been declared. {}",
+                    reference.lexeme()
+                )
+            }
+            PreProcessorError::InvalidConstantExpression(range) => {
+                write!(f, "Invalid constant expression {}", range)
+            }
+            PreProcessorError::DivisionByZero(range) => {
+                write!(f, "Division by zero in constant expression {}", range)
+            }
+            PreProcessorError::UndefinedConstant(reference, range) => write!(
+                f,
+                "Undefined constant '{}' in expression {}",
+                reference.lexeme().string(),
+                range
+            ),
+            PreProcessorError::FillValueOutOfRange(reference, found) => write!(
+                f,
+                "The value {} does not fit in a byte. {}",
+                found.lexeme().string(),
+                reference.lexeme()
+            ),
+            PreProcessorError::OrgMovesBackward(reference, current) => write!(
+                f,
+                "Cannot move the output address back from {:#X}. {}",
+                current,
+                reference.lexeme()
+            ),
+            PreProcessorError::ImageTooLarge(reference, end) => write!(
+                f,
+                "The output would reach address {:#X}, past the limit of {:#X}. {}",
+                end,
+                MAX_IMAGE_SIZE,
+                reference.lexeme()
+            ),
+            PreProcessorError::StringConstantAsNumber(reference) => write!(
+                f,
+                "The constant {} holds a string, but a number is expected. {}",
+                reference.lexeme().string(),
+                reference.lexeme()
+            ),
+            PreProcessorError::NumericConstantAsString(directive, reference) => write!(
+                f,
+                "The constant {} holds a number, but {} expects a string. {}",
+                reference.lexeme().string(),
+                directive.lexeme().string(),
+                reference.lexeme()
+            ),
+        };
+    }
+}
+
+impl fmt::Display for LexerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            LexerWarning::MixedIndentation(pos, source) => write!(
+                f,
+                "Indentation mixes tabs and spaces in {} at {}",
+                source, pos
+            ),
+        };
+    }
+}
+
+impl fmt::Display for PreProcessorWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            PreProcessorWarning::ShadowedConstant(variable) => write!(
+                f,
+                "The repeat variable shadows a constant of the same name. {}",
+                variable.lexeme()
+            ),
+        };
+    }
+}
+
+impl VXASMError for ImportError {}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ImportError::Lexer(e) => write!(f, "{}", e),
+            ImportError::FileNotFound(file) => {
+                write!(f, "Unable to find imported file. {}", file.lexeme())
+            }
+            ImportError::ImportCycle(cycle, file) => write!(
+                f,
+                "Import cycle detected: {}. {}",
+                cycle.join(" -> "),
+                file.lexeme()
+            ),
         };
     }
 }
@@ -293,6 +666,95 @@ impl fmt::Display for ParserError {
             ParserError::ExpectedCommaAfter(reference) => {
                 write!(f, "Unexpected ',' following {}.", reference.lexeme())
             }
+            ParserError::UnbalancedParenthesis(range) => {
+                write!(f, "Unbalanced parenthesis in operand {}", range)
+            }
+            ParserError::InvalidOperandExpression(range) => {
+                write!(f, "Invalid operand expression {}", range)
+            }
+            ParserError::DivisionByZero(range) => {
+                write!(f, "Division by zero in operand {}", range)
+            }
+        };
+    }
+}
+
+impl fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            OperandKind::Register => write!(f, "a register"),
+            OperandKind::Immediate => write!(f, "an immediate"),
+            OperandKind::Address => write!(f, "an address"),
+        };
+    }
+}
+
+impl VXASMError for AssembleError {}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            AssembleError::PreProcessor(e) => write!(f, "{}", e),
+            AssembleError::UndefinedSymbol(symbol) => {
+                write!(f, "Undefined symbol {}", symbol.lexeme())
+            }
+            AssembleError::Parser(e) => write!(f, "{}", e),
+            AssembleError::OperandCountMismatch {
+                expected,
+                found,
+                span,
+            } => write!(
+                f,
+                "Expected {} operands but found {}. {}",
+                expected, found, span
+            ),
+            AssembleError::OperandKindMismatch {
+                expected,
+                index,
+                span,
+            } => write!(f, "Operand {} should be {}. {}", index + 1, expected, span),
+            AssembleError::NotAFile(reference) => write!(
+                f,
+                "Only tokens lexed from a file can be assembled. {}",
+                reference.lexeme()
+            ),
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::processing::{Lexer, LexerBuilder};
+    use crate::text_mapping::FileInfoManager;
+
+    use super::*;
+
+    #[test]
+    fn test_render_diagnostic() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file("main.vsm".to_string(), "halt\nldi $r0, @\nhalt".to_string());
+
+        let err = Lexer::tokenize(f.clone()).unwrap_err();
+        let rendered = render_diagnostic(&err, &f);
+        let lines: alloc::vec::Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[1], " --> main.vsm:2:10");
+        assert_eq!(lines[3], "2 | ldi $r0, @");
+        assert_eq!(lines[4], "  |          ^");
+
+        let f = f_man.new_file("tabs.vsm".to_string(), "\tldi $r0, 1 ?".to_string());
+        let err = LexerBuilder::new()
+            .tab_width(8)
+            .build(Either::Left(f.clone()))
+            .process()
+            .unwrap_err();
+        let rendered = render_diagnostic(&err, &f);
+        let lines: alloc::vec::Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[1], " --> tabs.vsm:1:20");
+        assert_eq!(lines[3], "1 | \tldi $r0, 1 ?");
+        assert_eq!(lines[4], "  | \t           ^");
+    }
+}
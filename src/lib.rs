@@ -1,6 +1,7 @@
 #![no_std]
 extern crate alloc;
 
+pub mod disasm;
 pub mod error;
 pub mod processing;
 pub mod text_mapping;
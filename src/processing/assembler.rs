@@ -1,11 +1,352 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryInto;
 use digest::Digest;
+use either::Either;
+use hashbrown::{HashMap, HashSet};
 use vxl_iset::instruction::Instruction;
+use vxl_iset::instruction_arguments::{Address, Immediate, Register};
 use vxl_iset::vxl_file::{VXLFile, VXLHeader};
 
+use crate::error::{AssembleError, OperandKind, PreProcessorError};
+use crate::processing::{Parser, PreProcessor};
+use crate::text_mapping::TextRange;
+use crate::token::{Token, TokenType};
+
+/// Size metrics of an assembled program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AssemblyStats {
+    pub instruction_count: usize,
+    /// The length of the encoded output in bytes.
+    pub byte_size: usize,
+    /// The number of labels defined, including local labels.
+    pub label_count: usize,
+    pub directive_count: usize,
+}
+
+/// Assembles the tokens of a single file into machine code.
+///
+/// The tokens are preprocessed, resolving labels to their addresses, then parsed and encoded.
+/// Only the file the tokens came from is available, so programs that `%import` other files should
+/// go through the `ImportResolver` and `PreProcessor` instead.
+pub fn assemble(tokens: Vec<Token>) -> Result<Vec<u8>, AssembleError> {
+    return assemble_with_stats(tokens).map(|(bytes, _)| bytes);
+}
+
+/// Assembles the tokens of a single file as `assemble` does, also returning metrics of the
+/// program.
+pub fn assemble_with_stats(tokens: Vec<Token>) -> Result<(Vec<u8>, AssemblyStats), AssembleError> {
+    let directive_count = tokens
+        .iter()
+        .filter(|t| t.is_directive() && t.token_type() != TokenType::Identifier)
+        .count();
+
+    let processor = match preprocess(tokens)? {
+        Some(processor) => processor,
+        None => return Ok((Vec::new(), AssemblyStats::default())),
+    };
+
+    let label_count = processor.labels().len();
+    let tokens = processor.into_output();
+
+    let (assembler, instruction_count) = emit(tokens)?;
+    let bytes = assembler.dump_raw_bytes();
+
+    let stats = AssemblyStats {
+        instruction_count,
+        byte_size: bytes.len(),
+        label_count,
+        directive_count,
+    };
+
+    return Ok((bytes, stats));
+}
+
+/// Resolves the labels defined in the tokens of a single file, as `assemble` would.
+///
+/// Local labels are qualified by the label they are scoped to, as in `main.loop`.
+pub fn symbol_table(tokens: Vec<Token>) -> Result<BTreeMap<String, u64>, AssembleError> {
+    return match preprocess(tokens)? {
+        Some(processor) => Ok(processor.labels().clone()),
+        None => Ok(BTreeMap::new()),
+    };
+}
+
+/// Runs both preprocessor passes over the tokens of a single file, or returns None if there are
+/// no tokens.
+fn preprocess(tokens: Vec<Token>) -> Result<Option<PreProcessor>, AssembleError> {
+    let root = match tokens.first() {
+        Some(token) => match token.lexeme().source() {
+            Either::Left(file) => file.clone(),
+            Either::Right(_) => return Err(AssembleError::NotAFile(token.clone())),
+        },
+        None => return Ok(None),
+    };
+
+    let mut files = HashMap::new();
+    files.insert(root.clone(), tokens);
+
+    let mut processor = PreProcessor::new(files, HashSet::new());
+
+    let result = processor
+        .primary_process(&root)
+        .and_then(|_| processor.secondary_process());
+
+    return match result {
+        Ok(()) => Ok(Some(processor)),
+        Err(PreProcessorError::UndefinedLabel(symbol)) => {
+            Err(AssembleError::UndefinedSymbol(symbol))
+        }
+        Err(e) => Err(AssembleError::PreProcessor(e)),
+    };
+}
+
+/// Adds the preprocessed tokens to an assembler in order, returning it with the number of
+/// instructions added.
+///
+/// Data directives are emitted directly, while the instructions between them are parsed a run
+/// at a time.
+fn emit(tokens: Vec<Token>) -> Result<(Assembler, usize), AssembleError> {
+    let mut assembler = Assembler::new();
+    let mut instruction_count = 0;
+    let mut run = Vec::new();
+    let mut tokens = tokens.into_iter();
+
+    while let Some(token) = tokens.next() {
+        match token.token_type() {
+            TokenType::Ascii | TokenType::Asciiz => {
+                assembler = add_run(assembler, &mut run, &mut instruction_count)?;
+
+                let string = match tokens.next().map(|t| t.token_type()) {
+                    Some(TokenType::String(s)) => s,
+                    // The preprocessor always passes the directive on with its string
+                    _ => unreachable!("Expected a string after {}", token.lexeme()),
+                };
+
+                assembler = assembler.add_ascii(&string, token.token_type() == TokenType::Asciiz);
+            }
+            TokenType::Section => {
+                assembler = add_run(assembler, &mut run, &mut instruction_count)?;
+
+                assembler = match tokens.next().map(|t| t.token_type()) {
+                    Some(TokenType::String(name)) => assembler.section(&name),
+                    // The preprocessor passes the section name on as a string
+                    _ => unreachable!("Expected a section name after {}", token.lexeme()),
+                };
+            }
+            TokenType::Org => {
+                assembler = add_run(assembler, &mut run, &mut instruction_count)?;
+
+                assembler = match tokens.next().map(|t| t.token_type()) {
+                    Some(TokenType::UnsignedIntegerLiteral(address)) => {
+                        assembler.add_org(address as usize)
+                    }
+                    // The preprocessor folds the address to an unsigned literal
+                    _ => unreachable!("Expected an address after {}", token.lexeme()),
+                };
+            }
+            TokenType::Fill => {
+                assembler = add_run(assembler, &mut run, &mut instruction_count)?;
+
+                let count = tokens.next().map(|t| t.token_type());
+                let value = tokens.next().map(|t| t.token_type());
+
+                assembler = match (count, value) {
+                    (
+                        Some(TokenType::UnsignedIntegerLiteral(count)),
+                        Some(TokenType::UnsignedIntegerLiteral(value)),
+                    ) => assembler.add_fill(count as usize, value as u8),
+                    // The preprocessor folds both operands to unsigned literals
+                    _ => unreachable!("Expected a count and value after {}", token.lexeme()),
+                };
+            }
+            _ => run.push(token),
+        }
+    }
+
+    assembler = add_run(assembler, &mut run, &mut instruction_count)?;
+
+    return Ok((assembler, instruction_count));
+}
+
+/// Validates, parses and adds a run of instruction tokens, leaving the run empty.
+fn add_run(
+    assembler: Assembler,
+    run: &mut Vec<Token>,
+    instruction_count: &mut usize,
+) -> Result<Assembler, AssembleError> {
+    let tokens = core::mem::take(run);
+
+    validate_operands(&tokens)?;
+
+    let instructions = match Parser::with_tokens(tokens).parse() {
+        Ok(i) => i,
+        Err(e) => return Err(AssembleError::Parser(e)),
+    };
+
+    *instruction_count += instructions.len();
+
+    return Ok(assembler.add_instructions(instructions));
+}
+
+/// Checks that every instruction has as many operands as its opcode takes, each of the right
+/// kind.
+///
+/// Operands are the comma separated groups of tokens following an opcode, so that an operand
+/// expression in parentheses counts once.
+fn validate_operands(tokens: &[Token]) -> Result<(), AssembleError> {
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let opcode = match tokens[i].token_type() {
+            TokenType::Opcode(code) => code,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let mut operands: Vec<&[Token]> = Vec::new();
+        let mut depth = 0;
+        let mut start = i + 1;
+        let mut end = start;
+
+        while end < tokens.len() && is_operand_part(&tokens[end]) {
+            match tokens[end].token_type() {
+                TokenType::LParen => depth += 1,
+                TokenType::RParen => depth -= 1,
+                TokenType::Comma if depth == 0 => {
+                    operands.push(&tokens[start..end]);
+                    start = end + 1;
+                }
+                _ => (),
+            }
+
+            end += 1;
+        }
+
+        operands.push(&tokens[start..end]);
+        operands.retain(|operand| !operand.is_empty());
+
+        let expected = match (
+            Instruction::register_count(opcode),
+            Instruction::immediate_count(opcode),
+            Instruction::address_count(opcode),
+        ) {
+            (Some(r), Some(im), Some(a)) => r + im + a,
+            // Left for the parser to report as an unknown opcode
+            _ => {
+                i = end;
+                continue;
+            }
+        };
+
+        if operands.len() != expected {
+            return Err(AssembleError::OperandCountMismatch {
+                expected,
+                found: operands.len(),
+                span: span_of(&tokens[i..end]),
+            });
+        }
+
+        for (index, operand) in operands.iter().enumerate() {
+            let expected = match Instruction::get_type_for_index(opcode, index) {
+                Some(0) => OperandKind::Register,
+                Some(1) => OperandKind::Immediate,
+                _ => OperandKind::Address,
+            };
+
+            let is_register = operand.len() == 1 && operand[0].is_register();
+
+            if is_register != (expected == OperandKind::Register) {
+                return Err(AssembleError::OperandKindMismatch {
+                    expected,
+                    index,
+                    span: span_of(operand),
+                });
+            }
+        }
+
+        i = end;
+    }
+
+    return Ok(());
+}
+
+/// Whether the token can be part of an instruction's operands.
+fn is_operand_part(token: &Token) -> bool {
+    return match token.token_type() {
+        TokenType::Register(_)
+        | TokenType::UnsignedIntegerLiteral(_)
+        | TokenType::SignedIntegerLiteral(_)
+        | TokenType::FloatLiteral(_)
+        | TokenType::Comma
+        | TokenType::Operator(_)
+        | TokenType::LParen
+        | TokenType::RParen => true,
+        _ => false,
+    };
+}
+
+/// The number of bytes an instruction with the given opcode encodes to, which is what it moves
+/// the location counter by.
+///
+/// The length depends only on the kinds of operands the opcode takes, so it is measured on an
+/// instruction with zeroed operands. Unknown opcodes count as a single byte, and are left for
+/// the parser to report.
+pub(crate) fn encoded_length(opcode: u8) -> usize {
+    let (registers, immediates, addresses) = match (
+        Instruction::register_count(opcode),
+        Instruction::immediate_count(opcode),
+        Instruction::address_count(opcode),
+    ) {
+        (Some(r), Some(im), Some(a)) => (r, im, a),
+        _ => return 1,
+    };
+
+    let instruction = Instruction::new(
+        opcode,
+        (0..registers).map(|_| Register::R0).collect(),
+        (0..addresses).map(|_| Address::from(0u64)).collect(),
+        (0..immediates).map(|_| Immediate::from(0u64)).collect(),
+    );
+
+    return match instruction {
+        Some(instruction) => Vec::<u8>::from(instruction).len(),
+        None => 1,
+    };
+}
+
+/// The range covering a non-empty run of tokens.
+fn span_of(tokens: &[Token]) -> TextRange {
+    let first = tokens[0].lexeme();
+
+    return first
+        .merge(tokens[tokens.len() - 1].lexeme())
+        .unwrap_or_else(|| first.clone());
+}
+
+/// A piece of the output, emitted in the order it was added.
+enum Chunk {
+    Instruction(Instruction),
+    Data(Vec<u8>),
+    Org(usize),
+}
+
+/// The section output goes to until a `%section` directive switches it.
+pub const DEFAULT_SECTION: &str = ".text";
+
+/// A named region of the output with its own chunks.
+struct Section {
+    name: String,
+    chunks: Vec<Chunk>,
+}
+
 pub struct Assembler {
-    instructions: Vec<Instruction>,
+    sections: Vec<Section>,
+    current_section: usize,
     starting_offset: usize,
     sha2: bool,
 }
@@ -15,22 +356,87 @@ impl Assembler {
 
     pub fn new() -> Self {
         return Self {
-            instructions: Vec::new(),
+            sections: vec![Section {
+                name: String::from(DEFAULT_SECTION),
+                chunks: Vec::new(),
+            }],
+            current_section: 0,
             starting_offset: 0,
             sha2: false,
         };
     }
 
-    pub fn add_instructions(mut self, mut instructions: Vec<Instruction>) -> Self {
-        if self.instructions.is_empty() {
-            self.instructions = instructions;
-        } else {
-            self.instructions.append(&mut instructions);
+    /// Directs the output that follows to the named section, as produced by `%section name`.
+    ///
+    /// Sections are laid out in the order they were first used, after `.text`.
+    pub fn section(mut self, name: &str) -> Self {
+        self.current_section = match self.sections.iter().position(|s| s.name == name) {
+            Some(i) => i,
+            None => {
+                self.sections.push(Section {
+                    name: String::from(name),
+                    chunks: Vec::new(),
+                });
+
+                self.sections.len() - 1
+            }
+        };
+
+        return self;
+    }
+
+    fn chunks(&mut self) -> &mut Vec<Chunk> {
+        return &mut self.sections[self.current_section].chunks;
+    }
+
+    pub fn add_instructions(mut self, instructions: Vec<Instruction>) -> Self {
+        self.chunks()
+            .extend(instructions.into_iter().map(Chunk::Instruction));
+
+        return self;
+    }
+
+    /// Emits `count` copies of `value`, as produced by `%fill count, value`.
+    pub fn add_fill(mut self, count: usize, value: u8) -> Self {
+        if count != 0 {
+            self.chunks().push(Chunk::Data(vec![value; count]));
+        }
+
+        return self;
+    }
+
+    /// Emits the bytes of `string`, followed by a zero if `null_terminated`, as produced by
+    /// `%ascii` and `%asciiz`.
+    ///
+    /// Escape sequences are expected to have been replaced by the lexer already.
+    pub fn add_ascii(mut self, string: &str, null_terminated: bool) -> Self {
+        let mut bytes = string.as_bytes().to_vec();
+
+        if null_terminated {
+            bytes.push(0);
+        }
+
+        if !bytes.is_empty() {
+            self.chunks().push(Chunk::Data(bytes));
         }
 
         return self;
     }
 
+    /// Pads the current section with zeros up to `address` from its start, as produced by
+    /// `%org address`.
+    ///
+    /// Addresses count encoded bytes, as labels do, so an instruction before the `%org` moves
+    /// the section forward by its encoded length.
+    ///
+    /// The preprocessor rejects an `%org` that moves backwards or past `MAX_IMAGE_SIZE`, so an
+    /// address already passed emits nothing and the padding stays bounded.
+    pub fn add_org(mut self, address: usize) -> Self {
+        self.chunks().push(Chunk::Org(address));
+
+        return self;
+    }
+
     pub fn with_starting_offset(mut self, starting_offset: usize) -> Self {
         self.starting_offset = starting_offset;
 
@@ -52,8 +458,22 @@ impl Assembler {
     fn raw_bytes(self) -> Vec<u8> {
         let mut output = Vec::new();
 
-        for instruction in self.instructions {
-            output.append(&mut instruction.into());
+        for section in self.sections {
+            let mut bytes = Vec::new();
+
+            for chunk in section.chunks {
+                match chunk {
+                    Chunk::Instruction(instruction) => bytes.append(&mut instruction.into()),
+                    Chunk::Data(mut data) => bytes.append(&mut data),
+                    Chunk::Org(target) => {
+                        if target > bytes.len() {
+                            bytes.resize(target, 0);
+                        }
+                    }
+                }
+            }
+
+            output.append(&mut bytes);
         }
 
         return output;
@@ -94,3 +514,365 @@ impl Assembler {
         return output.try_into().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::processing::{Lexer, LexerBuilder, UnknownDirectivePolicy};
+    use crate::text_mapping::FileInfoManager;
+
+    use super::*;
+
+    fn assemble_source(source: &str) -> Result<Vec<u8>, AssembleError> {
+        let mut f_man = FileInfoManager::new();
+        let (_, tokens) = f_man
+            .tokenize_source("main.vsm".to_string(), source.to_string())
+            .unwrap();
+
+        return assemble(tokens);
+    }
+
+    #[test]
+    fn test_assemble() {
+        let mut expected: Vec<u8> = Instruction::new(
+            0x3,
+            vec![Register::R0],
+            vec![],
+            vec![Immediate::from(52u64)],
+        )
+        .unwrap()
+        .into();
+        expected.append(
+            &mut Instruction::new(0x37, vec![], vec![Address::from(0u64)], vec![])
+                .unwrap()
+                .into(),
+        );
+
+        assert_eq!(
+            assemble_source("START:\nldi $r0, 52\njmp START"),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_assemble_with_stats() {
+        let mut f_man = FileInfoManager::new();
+        let (_, tokens) = f_man
+            .tokenize_source(
+                "main.vsm".to_string(),
+                "%const A 5\nSTART:\nldi $r0, A\n.loop:\njmp .loop\nhalt".to_string(),
+            )
+            .unwrap();
+
+        let (bytes, stats) = assemble_with_stats(tokens).unwrap();
+
+        assert_eq!(
+            stats,
+            AssemblyStats {
+                instruction_count: 3,
+                byte_size: bytes.len(),
+                label_count: 2,
+                directive_count: 1,
+            }
+        );
+
+        let expected: usize = [
+            Instruction::new(0x3, vec![Register::R0], vec![], vec![Immediate::from(5u64)]),
+            Instruction::new(0x37, vec![], vec![Address::from(1u64)], vec![]),
+            Instruction::new(0x45, vec![], vec![], vec![]),
+        ]
+        .into_iter()
+        .map(|i| Into::<Vec<u8>>::into(i.unwrap()).len())
+        .sum();
+
+        assert_eq!(stats.byte_size, expected);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(matches!(
+            assemble_source("jmp NOWHERE"),
+            Err(AssembleError::UndefinedSymbol(_))
+        ));
+
+        let tokens = Lexer::tokenize_string("halt".to_string()).unwrap();
+
+        assert!(matches!(assemble(tokens), Err(AssembleError::NotAFile(_))));
+    }
+
+    #[test]
+    fn test_fill() {
+        let output = Assembler::new().add_fill(0x100, 0xFF).dump_raw_bytes();
+
+        assert_eq!(output.len(), 0x100);
+        assert!(output.iter().all(|b| *b == 0xFF));
+    }
+
+    #[test]
+    fn test_fill_zero_count() {
+        let output = Assembler::new().add_fill(0, 0xFF).dump_raw_bytes();
+
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_org_pads_with_zeros() {
+        let output = Assembler::new()
+            .add_fill(2, 0xFF)
+            .add_org(0x10)
+            .add_fill(1, 0xAA)
+            .dump_raw_bytes();
+
+        assert_eq!(output.len(), 0x11);
+        assert_eq!(&output[..2], &[0xFF, 0xFF]);
+        assert!(output[2..0x10].iter().all(|b| *b == 0));
+        assert_eq!(output[0x10], 0xAA);
+    }
+
+    #[test]
+    fn test_assemble_ascii() {
+        let halt: Vec<u8> = Instruction::new(0x45, vec![], vec![], vec![])
+            .unwrap()
+            .into();
+
+        assert_eq!(
+            assemble_source("halt\n%ascii \"hi\""),
+            Ok([&halt[..], b"hi"].concat())
+        );
+        assert_eq!(
+            assemble_source("%asciiz \"a\\tb\"\nhalt"),
+            Ok([&b"a\tb\0"[..], &halt[..]].concat())
+        );
+
+        let mut f_man = FileInfoManager::new();
+        let (_, tokens) = f_man
+            .tokenize_source(
+                "main.vsm".to_string(),
+                "%ascii \"hi\"\nhalt\nhalt".to_string(),
+            )
+            .unwrap();
+        let (bytes, stats) = assemble_with_stats(tokens).unwrap();
+
+        assert_eq!(stats.instruction_count, 2);
+        assert_eq!(stats.byte_size, bytes.len());
+        assert_eq!(bytes.len(), 2 + halt.len() * 2);
+    }
+
+    #[test]
+    fn test_assemble_fill() {
+        let halt: Vec<u8> = Instruction::new(0x45, vec![], vec![], vec![])
+            .unwrap()
+            .into();
+
+        let output = assemble_source("%fill 4, 0xAA\nhalt").unwrap();
+
+        assert_eq!(output.len(), 4 + halt.len());
+        assert_eq!(output, [&[0xAA; 4][..], &halt[..]].concat());
+
+        let jmp: Vec<u8> = Instruction::new(0x37, vec![], vec![Address::from(2u64)], vec![])
+            .unwrap()
+            .into();
+
+        assert_eq!(
+            assemble_source("%fill 2, 0\nL:\nhalt\njmp L"),
+            Ok([&[0, 0][..], &halt[..], &jmp[..]].concat())
+        );
+    }
+
+    #[test]
+    fn test_assemble_raw_directive() {
+        let halt: Vec<u8> = Instruction::new(0x45, vec![], vec![], vec![])
+            .unwrap()
+            .into();
+
+        let assemble_ignoring = |source: &str| {
+            let mut f_man = FileInfoManager::new();
+            let f = f_man.new_file("main.vsm".to_string(), source.to_string());
+            let mut lexer = LexerBuilder::new()
+                .unknown_directive_policy(UnknownDirectivePolicy::Ignore)
+                .build(Either::Left(f));
+
+            lexer.process().unwrap();
+
+            return assemble(lexer.into_tokens());
+        };
+
+        assert_eq!(assemble_ignoring("%mytool_hint 3\nhalt"), Ok(halt.clone()));
+        assert_eq!(
+            assemble_ignoring("%mytool_hint foo, (1 + 2)\nhalt\n%mytool_hint"),
+            Ok(halt)
+        );
+    }
+
+    #[test]
+    fn test_operand_count() {
+        match assemble_source("halt\nmalloc $r0\nhalt") {
+            Err(AssembleError::OperandCountMismatch {
+                expected,
+                found,
+                span,
+            }) => {
+                assert_eq!((expected, found), (2, 1));
+                assert_eq!(span.string(), "malloc $r0");
+            }
+            other => panic!("Expected an operand count mismatch, found {:?}", other),
+        }
+
+        assert!(matches!(
+            assemble_source("halt $r0"),
+            Err(AssembleError::OperandCountMismatch {
+                expected: 0,
+                found: 1,
+                ..
+            })
+        ));
+        assert!(assemble_source("ldi $r0, (2 + 3 * 4)").is_ok());
+    }
+
+    #[test]
+    fn test_operand_kind() {
+        match assemble_source("ldi $r0, $r1") {
+            Err(AssembleError::OperandKindMismatch {
+                expected,
+                index,
+                span,
+            }) => {
+                assert_eq!((expected, index), (OperandKind::Immediate, 1));
+                assert_eq!(span.string(), "r1");
+            }
+            other => panic!("Expected an operand kind mismatch, found {:?}", other),
+        }
+
+        assert!(matches!(
+            assemble_source("malloc $r0, 4"),
+            Err(AssembleError::OperandKindMismatch {
+                expected: OperandKind::Register,
+                index: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let mut f_man = FileInfoManager::new();
+        let (_, tokens) = f_man
+            .tokenize_source(
+                "main.vsm".to_string(),
+                "START:\nldi $r0, 1\n.loop:\nldi $r1, 2\njmp .loop\nEND:\nhalt".to_string(),
+            )
+            .unwrap();
+
+        let symbols = symbol_table(tokens).unwrap();
+
+        assert_eq!(
+            symbols.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("END".to_string(), 29),
+                ("START".to_string(), 0),
+                ("START.loop".to_string(), 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_layout() {
+        let halt = || Instruction::new(0x45, vec![], vec![], vec![]).unwrap();
+        let halt_len = Vec::<u8>::from(halt()).len();
+
+        let output = Assembler::new()
+            .add_instructions(vec![halt()])
+            .section(".data")
+            .add_fill(4, 0xAA)
+            .section(DEFAULT_SECTION)
+            .add_instructions(vec![halt()])
+            .dump_raw_bytes();
+
+        assert_eq!(output.len(), halt_len * 2 + 4);
+        assert_eq!(
+            &output[..halt_len * 2],
+            &[halt().into(), Vec::<u8>::from(halt())].concat()[..]
+        );
+        assert_eq!(&output[halt_len * 2..], &[0xAA; 4]);
+    }
+
+    #[test]
+    fn test_org_counts_instructions() {
+        let halt = || Instruction::new(0x45, vec![], vec![], vec![]).unwrap();
+        let halt_bytes: Vec<u8> = halt().into();
+
+        let output = Assembler::new()
+            .add_instructions(vec![halt()])
+            .add_org(4)
+            .add_instructions(vec![halt()])
+            .dump_raw_bytes();
+
+        assert_eq!(
+            output,
+            [&halt_bytes[..], &[0, 0, 0][..], &halt_bytes[..]].concat()
+        );
+    }
+
+    #[test]
+    fn test_assemble_org() {
+        let halt: Vec<u8> = Instruction::new(0x45, vec![], vec![], vec![])
+            .unwrap()
+            .into();
+        let jmp: Vec<u8> = Instruction::new(0x37, vec![], vec![Address::from(0x100u64)], vec![])
+            .unwrap()
+            .into();
+
+        let output = assemble_source("%org 0x100\nL:\nhalt\njmp L").unwrap();
+
+        assert_eq!(output.len(), 0x100 + halt.len() + jmp.len());
+        assert!(output[..0x100].iter().all(|b| *b == 0));
+        assert_eq!(&output[0x100..], &[&halt[..], &jmp[..]].concat()[..]);
+
+        assert_eq!(
+            assemble_source("%org 16\nhalt"),
+            Ok([&[0; 16][..], &halt[..]].concat())
+        );
+    }
+
+    #[test]
+    fn test_assemble_sections() {
+        let halt: Vec<u8> = Instruction::new(0x45, vec![], vec![], vec![])
+            .unwrap()
+            .into();
+        let jmp: Vec<u8> = Instruction::new(0x37, vec![], vec![Address::from(1u64)], vec![])
+            .unwrap()
+            .into();
+
+        assert_eq!(
+            assemble_source("halt\n%section .data\nMSG:\n%ascii \"hi\"\n%section .text\nL:\njmp L"),
+            Ok([&halt[..], &jmp[..], b"hi"].concat())
+        );
+
+        let jmp_data: Vec<u8> = Instruction::new(
+            0x37,
+            vec![],
+            vec![Address::from((halt.len() + jmp.len()) as u64)],
+            vec![],
+        )
+        .unwrap()
+        .into();
+
+        assert_eq!(
+            assemble_source("halt\n%section .data\nMSG:\n%ascii \"hi\"\n%section .text\njmp MSG"),
+            Ok([&halt[..], &jmp_data[..], b"hi"].concat())
+        );
+    }
+
+    #[test]
+    fn test_org_within_section() {
+        let output = Assembler::new()
+            .add_fill(2, 0xFF)
+            .section(".data")
+            .add_org(4)
+            .add_fill(1, 0xAA)
+            .dump_raw_bytes();
+
+        assert_eq!(output, vec![0xFF, 0xFF, 0, 0, 0, 0, 0xAA]);
+    }
+}
@@ -0,0 +1,246 @@
+use core::iter::Peekable;
+
+use alloc::string::String;
+use hashbrown::HashMap;
+
+use crate::error::PreProcessorError;
+use crate::text_mapping::TextRange;
+use crate::token::{Operator, Token, TokenType};
+
+type ExpressionResult<T> = Result<T, PreProcessorError>;
+
+/// Folds the value following a `%const` name into a single literal token.
+///
+/// A lone literal or constant reference is passed through unchanged. Anything longer is evaluated
+/// as integer arithmetic, with operands being integer literals, previously defined constants and
+/// parenthesised sub-expressions. The expression ends at the first token that cannot continue it.
+pub(crate) fn evaluate_constant<T: Iterator<Item = Token>>(
+    constant_identifier: &Token,
+    tokens: &mut Peekable<T>,
+    constants: &HashMap<String, Token>,
+) -> ExpressionResult<Token> {
+    return match tokens.next() {
+        Some(first) => evaluate_constant_from(constant_identifier, first, tokens, constants),
+        None => Err(PreProcessorError::ExpectedUnsignedIntegerFoundEOF(
+            constant_identifier.clone(),
+        )),
+    };
+}
+
+/// Evaluates an expression whose first token has already been taken from `tokens`.
+pub(crate) fn evaluate_constant_from<T: Iterator<Item = Token>>(
+    constant_identifier: &Token,
+    first: Token,
+    tokens: &mut Peekable<T>,
+    constants: &HashMap<String, Token>,
+) -> ExpressionResult<Token> {
+    let lone = match first.token_type() {
+        TokenType::UnsignedIntegerLiteral(_)
+        | TokenType::SignedIntegerLiteral(_)
+        | TokenType::FloatLiteral(_)
+        | TokenType::String(_) => Some(first.clone()),
+        TokenType::Identifier => constants.get(&first.lexeme().string()).cloned(),
+        _ => None,
+    };
+
+    if let Some(value) = lone {
+        if !continues_expression(tokens.peek()) {
+            return Ok(value);
+        }
+    }
+
+    let mut evaluator = Evaluator {
+        constant_identifier,
+        tokens,
+        constants,
+        start: first.lexeme().clone(),
+        end: first.lexeme().clone(),
+    };
+
+    let lhs = evaluator.operand(first)?;
+    let value = evaluator.fold(lhs, 0)?.value;
+    let range = evaluator.span();
+
+    if value >= 0 {
+        if let Ok(v) = u64::try_from(value) {
+            return Ok(Token::new(TokenType::UnsignedIntegerLiteral(v), range));
+        }
+    } else if let Ok(v) = i64::try_from(value) {
+        return Ok(Token::new(TokenType::SignedIntegerLiteral(v), range));
+    }
+
+    return Err(PreProcessorError::InvalidConstantExpression(range));
+}
+
+/// Whether the token can follow an operand within an expression.
+///
+/// A negative literal directly after an operand, as in `A-1`, is a subtraction written without
+/// spaces.
+fn continues_expression(token: Option<&Token>) -> bool {
+    return match token {
+        Some(t) => match t.token_type() {
+            TokenType::Operator(_) => true,
+            TokenType::SignedIntegerLiteral(v) => v < 0 && t.lexeme().string().starts_with('-'),
+            _ => false,
+        },
+        None => false,
+    };
+}
+
+/// An intermediate result, tracking whether it came from signed operands.
+///
+/// Right shifts are arithmetic on signed values and logical on unsigned ones, as on the ISA.
+#[derive(Clone, Copy)]
+struct Value {
+    value: i128,
+    signed: bool,
+}
+
+impl Value {
+    const fn unsigned(value: i128) -> Self {
+        return Self {
+            value,
+            signed: false,
+        };
+    }
+
+    const fn signed(value: i128) -> Self {
+        return Self {
+            value,
+            signed: true,
+        };
+    }
+}
+
+struct Evaluator<'a, T: Iterator<Item = Token>> {
+    constant_identifier: &'a Token,
+    tokens: &'a mut Peekable<T>,
+    constants: &'a HashMap<String, Token>,
+    start: TextRange,
+    end: TextRange,
+}
+
+impl<'a, T: Iterator<Item = Token>> Evaluator<'a, T> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next()?;
+        self.end = token.lexeme().clone();
+
+        return Some(token);
+    }
+
+    /// The range covering the expression so far.
+    fn span(&self) -> TextRange {
+        return self.start.merge(&self.end).unwrap_or(self.start.clone());
+    }
+
+    /// Applies every operator binding at least as tightly as `min_precedence` to `lhs`.
+    fn fold(&mut self, mut lhs: Value, min_precedence: u8) -> ExpressionResult<Value> {
+        while continues_expression(self.tokens.peek()) {
+            let token = self.tokens.peek().unwrap().clone();
+
+            let (operator, rhs) = match token.token_type() {
+                TokenType::Operator(operator) => {
+                    if operator.precedence() < min_precedence {
+                        break;
+                    }
+
+                    self.next();
+
+                    let operand = self.next_operand()?;
+
+                    (operator, self.fold(operand, operator.precedence() + 1)?)
+                }
+                TokenType::SignedIntegerLiteral(v) => {
+                    if Operator::Subtract.precedence() < min_precedence {
+                        break;
+                    }
+
+                    self.next();
+
+                    let operand = Value::signed(-(v as i128));
+
+                    (
+                        Operator::Subtract,
+                        self.fold(operand, Operator::Subtract.precedence() + 1)?,
+                    )
+                }
+                _ => unreachable!(),
+            };
+
+            lhs = self.apply(operator, lhs, rhs)?;
+        }
+
+        return Ok(lhs);
+    }
+
+    fn next_operand(&mut self) -> ExpressionResult<Value> {
+        return match self.next() {
+            Some(token) => self.operand(token),
+            None => Err(PreProcessorError::InvalidConstantExpression(self.span())),
+        };
+    }
+
+    fn operand(&mut self, token: Token) -> ExpressionResult<Value> {
+        return match token.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) => Ok(Value::unsigned(v as i128)),
+            TokenType::SignedIntegerLiteral(v) => Ok(Value::signed(v as i128)),
+            TokenType::Identifier => match self.constants.get(&token.lexeme().string()) {
+                Some(value) => match value.token_type() {
+                    TokenType::UnsignedIntegerLiteral(v) => Ok(Value::unsigned(v as i128)),
+                    TokenType::SignedIntegerLiteral(v) => Ok(Value::signed(v as i128)),
+                    TokenType::String(_) => Err(PreProcessorError::StringConstantAsNumber(token)),
+                    _ => Err(PreProcessorError::InvalidConstantValue(
+                        self.constant_identifier.clone(),
+                        token,
+                    )),
+                },
+                None => Err(PreProcessorError::UndefinedConstant(token, self.span())),
+            },
+            TokenType::Operator(Operator::Subtract) => {
+                let operand = self.next_operand()?;
+
+                Ok(Value::signed(-operand.value))
+            }
+            TokenType::LParen => {
+                let operand = self.next_operand()?;
+                let value = self.fold(operand, 0)?;
+
+                match self.next() {
+                    Some(t) if t.is_r_paren() => Ok(value),
+                    _ => Err(PreProcessorError::InvalidConstantExpression(self.span())),
+                }
+            }
+            _ => Err(PreProcessorError::InvalidConstantValue(
+                self.constant_identifier.clone(),
+                token,
+            )),
+        };
+    }
+
+    fn apply(&self, operator: Operator, lhs: Value, rhs: Value) -> ExpressionResult<Value> {
+        if rhs.value == 0 && (operator == Operator::Divide || operator == Operator::Remainder) {
+            return Err(PreProcessorError::DivisionByZero(self.span()));
+        }
+
+        let signed = lhs.signed || rhs.signed;
+
+        let value = if operator == Operator::ShiftRight && !lhs.signed {
+            // Shift the 64 bit pattern, so an unsigned value that went negative fills with zeros.
+            let bits = u64::try_from(lhs.value)
+                .ok()
+                .or_else(|| i64::try_from(lhs.value).ok().map(|v| v as u64));
+
+            match (bits, u32::try_from(rhs.value)) {
+                (Some(bits), Ok(shift)) => bits.checked_shr(shift).map(|v| v as i128),
+                _ => None,
+            }
+        } else {
+            operator.apply(lhs.value, rhs.value)
+        };
+
+        return match value {
+            Some(value) => Ok(Value { value, signed }),
+            None => Err(PreProcessorError::InvalidConstantExpression(self.span())),
+        };
+    }
+}
@@ -0,0 +1,314 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use either::Either;
+
+use crate::text_mapping::Source;
+use crate::token::{Token, TokenType};
+
+/// The indentation of instructions, in spaces. Labels and directives are not indented.
+const INDENT: usize = 4;
+
+/// A line of formatted output.
+struct Line {
+    indent: usize,
+    text: String,
+    /// Whether the line holds only a comment, which takes the indentation of the next line.
+    comment: bool,
+}
+
+/// Reconstructs assembly from a token stream in a canonical layout.
+///
+/// Every instruction, label and directive is placed on its own line, with instructions indented
+/// and their operands aligned after the longest mnemonic. Mnemonics and registers are lowercased,
+/// commas are followed by a single space and runs of blank lines collapse to one. Literals keep
+/// their original spelling.
+///
+/// Comments are kept when the tokens include them. They are written as `#` line comments, or as
+/// `/* */` block comments when they span several lines. Newline and end of input tokens are not
+/// needed, lines are taken from the positions of the tokens.
+pub fn format_tokens(tokens: &[Token]) -> String {
+    let mut spellings = Vec::with_capacity(tokens.len());
+    let mut text: Option<(&Source, Vec<char>)> = None;
+    let mut previous_end = 0;
+
+    for token in tokens {
+        let source = token.lexeme().source();
+
+        if text.as_ref().is_none_or(|(s, _)| *s != source) {
+            let contents = match source {
+                Either::Left(file) => file.contents().as_str(),
+                Either::Right(string) => string.as_str(),
+            };
+
+            text = Some((source, contents.chars().collect()));
+            previous_end = 0;
+        }
+
+        let chars = &text.as_ref().unwrap().1;
+
+        spellings.push(spelling(token, chars, previous_end));
+        previous_end = token.lexeme().end().index();
+    }
+
+    let width = tokens
+        .iter()
+        .zip(&spellings)
+        .filter(|(t, _)| t.is_opcode())
+        .map(|(_, s)| s.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut line: Option<Line> = None;
+    let mut last: Option<&Token> = None;
+    let mut line_break = false;
+
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_newline() || token.is_eof() {
+            continue;
+        }
+
+        let same_row = last.is_some_and(|l| {
+            l.lexeme().source() == token.lexeme().source()
+                && l.lexeme().end().row() == token.lexeme().start().row()
+        });
+
+        let blank_line = last.is_some_and(|l| {
+            l.lexeme().source() == token.lexeme().source()
+                && token.lexeme().start().row() > l.lexeme().end().row() + 1
+        });
+
+        if token.is_comment() {
+            let comment = comment_text(&token.lexeme().string());
+
+            match line.as_mut() {
+                Some(current) if same_row => {
+                    current.text.push(' ');
+                    current.text.push_str(&comment);
+                    line_break = true;
+                }
+                _ => {
+                    lines.extend(line.take());
+
+                    if blank_line && !lines.is_empty() {
+                        lines.push(Line::blank());
+                    }
+
+                    lines.push(Line {
+                        indent: 0,
+                        text: comment,
+                        comment: true,
+                    });
+                }
+            }
+
+            last = Some(token);
+            continue;
+        }
+
+        let label = (token.is_identifier() || token.is_local_label())
+            && tokens.get(i + 1).is_some_and(|t| t.is_colon());
+
+        let starts_line = line.is_none()
+            || line_break
+            || !same_row
+            || token.is_opcode()
+            || label
+            || (token.is_directive() && !token.is_identifier())
+            || token.is_raw_directive();
+
+        let text = match token.token_type() {
+            TokenType::Opcode(_) | TokenType::Register(_) | TokenType::RegisterRange(..) => {
+                spellings[i].to_lowercase()
+            }
+            _ => spellings[i].clone(),
+        };
+
+        if starts_line {
+            lines.extend(line.take());
+
+            if blank_line && !lines.is_empty() {
+                lines.push(Line::blank());
+            }
+
+            let directive =
+                (token.is_directive() && !token.is_identifier()) || token.is_raw_directive();
+            let indent = if label || directive { 0 } else { INDENT };
+
+            line = Some(Line {
+                indent,
+                text,
+                comment: false,
+            });
+            line_break = false;
+        } else {
+            let current = line.as_mut().unwrap();
+            let previous = last.unwrap();
+
+            if previous.is_opcode() {
+                let padding = width - current.text.chars().count() + 1;
+                current.text.extend(core::iter::repeat_n(' ', padding));
+            } else if !(token.is_comma()
+                || token.is_colon()
+                || token.is_r_paren()
+                || previous.is_l_paren())
+            {
+                current.text.push(' ');
+            }
+
+            current.text.push_str(&text);
+        }
+
+        if token.is_colon() && last.is_some_and(|l| l.is_identifier() || l.is_local_label()) {
+            line_break = true;
+        }
+
+        last = Some(token);
+    }
+
+    lines.extend(line);
+
+    // Comments on their own line are indented like the line they precede.
+    let mut indent = 0;
+
+    for line in lines.iter_mut().rev() {
+        if line.comment {
+            line.indent = indent;
+        } else if !line.text.is_empty() {
+            indent = line.indent;
+        }
+    }
+
+    let mut output = String::new();
+
+    for line in &lines {
+        if !line.text.is_empty() {
+            output.extend(core::iter::repeat_n(' ', line.indent));
+            output.push_str(&line.text);
+        }
+
+        output.push('\n');
+    }
+
+    return output;
+}
+
+impl Line {
+    fn blank() -> Self {
+        return Self {
+            indent: 0,
+            text: String::new(),
+            comment: false,
+        };
+    }
+}
+
+/// The text of a token as written, including prefixes such as `$`, `%` and `0x` and the quotes
+/// around strings, which are not part of the lexeme.
+fn spelling(token: &Token, chars: &[char], previous_end: usize) -> String {
+    let range = token.lexeme();
+    let end = range.end().index().min(chars.len());
+    let mut start = range.start().index().min(end);
+
+    while start > previous_end && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+
+    let mut spelling: String = chars[start..end].iter().collect();
+
+    if let Some(quote) = spelling.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        if chars.get(end) == Some(&quote) {
+            spelling.push(quote);
+        }
+    }
+
+    return spelling;
+}
+
+/// Renders the contents of a comment in its canonical form.
+fn comment_text(contents: &str) -> String {
+    if contents.contains('\n') || contents.contains('\r') {
+        let mut text = String::from("/*");
+        text.push_str(contents);
+        text.push_str("*/");
+
+        return text;
+    }
+
+    let contents = contents.trim();
+    let mut text = String::from("#");
+
+    if !contents.is_empty() {
+        text.push(' ');
+        text.push_str(contents);
+    }
+
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::processing::LexerBuilder;
+    use crate::text_mapping::FileInfoManager;
+
+    use super::*;
+
+    fn format(input: &str) -> String {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file("root.asm".to_string(), input.to_string());
+
+        let mut lexer = LexerBuilder::new()
+            .keep_comments(true)
+            .build(Either::Left(f));
+
+        lexer.process().unwrap();
+
+        return format_tokens(&lexer.into_tokens());
+    }
+
+    #[test]
+    fn test_format_tokens() {
+        let input = concat!(
+            "  START:ldi   $R0 ,0x10 # load\n",
+            "\n\n",
+            "   halt\n",
+            "#done\n",
+            "%const  A   ( 2 + 3 )\n",
+            ".loop: jmp .loop\n",
+            "%ascii \"a \\\"b\\\"\" malloc $r1,$sp",
+        );
+
+        assert_eq!(
+            format(input),
+            concat!(
+                "START:\n",
+                "    ldi    $r0, 0x10 # load\n",
+                "\n",
+                "    halt\n",
+                "# done\n",
+                "%const A (2 + 3)\n",
+                ".loop:\n",
+                "    jmp    .loop\n",
+                "%ascii \"a \\\"b\\\"\"\n",
+                "    malloc $r1, $sp\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_idempotent() {
+        let input = concat!(
+            "START: ldi $r0,  0u8:255\n",
+            "  # indented\n",
+            "\tjmp START /* a\n b */\n",
+            "\n\n\n",
+            "halt",
+        );
+
+        let formatted = format(input);
+
+        assert_eq!(format(&formatted), formatted);
+    }
+}
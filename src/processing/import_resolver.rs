@@ -0,0 +1,214 @@
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use hashbrown::HashMap;
+
+use crate::error::ImportError;
+use crate::processing::pre_processor::FORCE_IMPORT;
+use crate::processing::Lexer;
+use crate::text_mapping::{FileInfo, FileInfoManager};
+use crate::token::{Token, TokenType};
+
+type ImportResult<T> = Result<T, ImportError>;
+
+/// Loads and lexes the files referenced by `%import` directives.
+///
+/// The resolver closure maps an imported file name to its contents. The resulting tokens are
+/// keyed by file and can be handed straight to the `PreProcessor`, which splices them in place
+/// of each import.
+pub struct ImportResolver<'a, F: FnMut(&str) -> Option<String>> {
+    file_manager: &'a mut FileInfoManager,
+    resolver: F,
+    tokens: HashMap<Rc<FileInfo>, Vec<Token>>,
+    stack: Vec<Rc<FileInfo>>,
+}
+
+impl<'a, F: FnMut(&str) -> Option<String>> ImportResolver<'a, F> {
+    pub fn new(file_manager: &'a mut FileInfoManager, resolver: F) -> Self {
+        return Self {
+            file_manager,
+            resolver,
+            tokens: HashMap::new(),
+            stack: Vec::new(),
+        };
+    }
+
+    /// Lexes the root file and every file it imports, directly or transitively.
+    ///
+    /// Files already registered with the file manager are reused instead of being resolved again.
+    pub fn resolve(
+        mut self,
+        root: &Rc<FileInfo>,
+    ) -> ImportResult<HashMap<Rc<FileInfo>, Vec<Token>>> {
+        self.resolve_file(root.clone())?;
+
+        return Ok(self.tokens);
+    }
+
+    fn resolve_file(&mut self, file: Rc<FileInfo>) -> ImportResult<()> {
+        let tokens = match Lexer::tokenize(file.clone()) {
+            Ok(t) => t,
+            Err(e) => return Err(ImportError::Lexer(e)),
+        };
+
+        let mut imports = Vec::new();
+
+        for (i, token) in tokens.iter().enumerate() {
+            if !token.is_import() {
+                continue;
+            }
+
+            let name = match tokens.get(i + 1) {
+                Some(t) if t.is_identifier() && t.lexeme().string() == FORCE_IMPORT => {
+                    tokens.get(i + 2)
+                }
+                next => next,
+            };
+
+            if let Some(name) = name.filter(|t| t.is_string()) {
+                imports.push(name.clone());
+            }
+        }
+
+        self.tokens.insert(file.clone(), tokens);
+        self.stack.push(file);
+
+        for import in imports {
+            let name = match import.token_type() {
+                TokenType::String(s) => s,
+                _ => unreachable!(),
+            };
+
+            if let Some(i) = self.stack.iter().position(|f| f.name().as_str() == &*name) {
+                let mut cycle: Vec<String> =
+                    self.stack[i..].iter().map(|f| f.name().clone()).collect();
+                cycle.push(String::from(&*name));
+
+                return Err(ImportError::ImportCycle(cycle, import));
+            }
+
+            let file = match self.file_manager.get_file_info(&name) {
+                Some(f) => {
+                    if self.tokens.contains_key(&f) {
+                        continue;
+                    }
+
+                    f
+                }
+                None => match (self.resolver)(&name) {
+                    Some(contents) => self.file_manager.new_file(String::from(&*name), contents),
+                    None => return Err(ImportError::FileNotFound(import)),
+                },
+            };
+
+            self.resolve_file(file)?;
+        }
+
+        self.stack.pop();
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use either::Either;
+    use hashbrown::HashSet;
+
+    use crate::processing::PreProcessor;
+
+    use super::*;
+
+    fn files(name: &str) -> Option<String> {
+        return match name {
+            "b.vsm" => Some("ldi $r0, 0u1".to_string()),
+            "c.vsm" => Some("%import \"d.vsm\"".to_string()),
+            "d.vsm" => Some("%import \"c.vsm\"".to_string()),
+            "e.vsm" => Some("%import force \"b.vsm\"\nhalt".to_string()),
+            _ => None,
+        };
+    }
+
+    #[test]
+    fn test_import_keeps_file_info() {
+        let mut f_man = FileInfoManager::new();
+        let a = f_man.new_file("a.vsm".to_string(), "%import \"b.vsm\"\nhalt".to_string());
+
+        let tokens = ImportResolver::new(&mut f_man, files).resolve(&a).unwrap();
+        let output = PreProcessor::new(tokens, HashSet::new()).run(&a).unwrap();
+
+        let b = f_man.get_file_info("b.vsm").unwrap();
+
+        assert_eq!(
+            output.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(vxl_iset::instruction_arguments::Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Opcode(0x45),
+            ]
+        );
+
+        for token in &output[..4] {
+            assert_eq!(token.lexeme().source(), &Either::Left(b.clone()));
+        }
+
+        assert_eq!(output[4].lexeme().source(), &Either::Left(a.clone()));
+    }
+
+    #[test]
+    fn test_import_force() {
+        let mut f_man = FileInfoManager::new();
+        let a = f_man.new_file("a.vsm".to_string(), "%import \"e.vsm\"".to_string());
+
+        let tokens = ImportResolver::new(&mut f_man, files).resolve(&a).unwrap();
+
+        assert!(tokens.contains_key(&f_man.get_file_info("b.vsm").unwrap()));
+    }
+
+    #[test]
+    fn test_token_file() {
+        let mut f_man = FileInfoManager::new();
+        let a = f_man.new_file("a.vsm".to_string(), "%import \"b.vsm\"\nhalt".to_string());
+
+        let tokens = ImportResolver::new(&mut f_man, files).resolve(&a).unwrap();
+        let output = PreProcessor::new(tokens, HashSet::new()).run(&a).unwrap();
+
+        let b = f_man.get_file_info("b.vsm").unwrap();
+
+        assert_eq!(output[0].file(), Some(&b));
+        assert_eq!(output[0].file_name(), Some("b.vsm"));
+        assert_eq!(output[4].file_name(), Some("a.vsm"));
+
+        let tokens = Lexer::tokenize_string("halt".to_string()).unwrap();
+
+        assert_eq!(tokens[0].file_name(), None);
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let mut f_man = FileInfoManager::new();
+        let a = f_man.new_file("a.vsm".to_string(), "%import \"c.vsm\"".to_string());
+
+        match ImportResolver::new(&mut f_man, files).resolve(&a) {
+            Err(ImportError::ImportCycle(cycle, _)) => {
+                assert_eq!(cycle, vec!["c.vsm", "d.vsm", "c.vsm"]);
+            }
+            _ => panic!("Expected an import cycle"),
+        }
+    }
+
+    #[test]
+    fn test_import_not_found() {
+        let mut f_man = FileInfoManager::new();
+        let a = f_man.new_file("a.vsm".to_string(), "%import \"missing.vsm\"".to_string());
+
+        assert!(matches!(
+            ImportResolver::new(&mut f_man, files).resolve(&a),
+            Err(ImportError::FileNotFound(_))
+        ));
+    }
+}
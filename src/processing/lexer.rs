@@ -1,3 +1,4 @@
+use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -5,37 +6,161 @@ use either::Either;
 use vxl_iset::instruction::Instruction;
 use vxl_iset::instruction_arguments::Register;
 
-use crate::error::LexerError;
+use crate::disasm::REGISTERS;
+use crate::error::{LexerError, LexerWarning};
 use crate::text_mapping::{AssemblyString, FilePtr, Position, Source, TextRange};
-use crate::token::{Token, TokenType};
+use crate::token::{Operator, Token, TokenType, DIRECTIVE_NAMES};
 
 type LexerResult<T> = Result<T, LexerError>;
 
+/// The approximate number of characters per token, used to reserve space for the output.
+const TOKEN_CAPACITY_RATIO: usize = 3;
+
+/// The general purpose registers ($r0, $r1, ...) defined by the instruction set, in order.
+const GENERAL_REGISTERS: &[Register] = REGISTERS.split_at(Register::R0 as usize).1;
+
+/// The bit widths that may annotate an integer literal, as in `0u8:255`.
+const INTEGER_WIDTHS: [u64; 4] = [8, 16, 32, 64];
+
+/// The most hex digits that fit in a 64 bit literal.
+pub(crate) const MAX_HEX_DIGITS: usize = 16;
+
+/// The characters that may follow a `0` to give the type of a literal, as in `0x1F`.
+pub const DEFAULT_RADIX_PREFIXES: [(char, NumericType); 5] = [
+    ('x', NumericType::Hex),
+    ('b', NumericType::Bin),
+    ('i', NumericType::Signed),
+    ('u', NumericType::Unsigned),
+    ('f', NumericType::Float),
+];
+
+/// Characters with a meaning to the lexer, which cannot start comments.
+const RESERVED_CHARS: &[char] = &[
+    '$', ',', ':', '%', '"', '\'', '.', '_', '(', ')', '+', '-', '*', '/', '&', '|', '^', '<', '>',
+];
+
+/// A way of writing comments, several of which may be accepted at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// A comment from the character to the end of the line, as in `# comment`.
+    Line(char),
+    /// A comment from the string to the end of the line, as in `// comment`.
+    LineStr(&'static str),
+    /// A comment between two strings, which may be nested and span several lines.
+    Block {
+        open: &'static str,
+        close: &'static str,
+    },
+}
+
+/// The comment styles accepted by default, `#` line comments and `/* */` block comments.
+const DEFAULT_COMMENT_STYLES: [CommentStyle; 2] = [
+    CommentStyle::Line('#'),
+    CommentStyle::Block {
+        open: "/*",
+        close: "*/",
+    },
+];
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NumericType {
     Signed,
     Unsigned,
     Float,
+    /// Bare digit runs are hexadecimal. Words made only of hex digits, such as `ff`, are read as
+    /// numbers unless they name an opcode. Explicit prefixes like `0b` and `0f` still apply.
+    Hex,
+    /// Bare digit runs are binary.
+    Bin,
+}
+
+/// What the lexer does with a `%` directive it does not know.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownDirectivePolicy {
+    /// Report the directive as an error.
+    Error,
+    /// Produce a raw directive token, which the preprocessor skips along with the rest of its
+    /// line. This suits sources that still hold directives meant for another tool, as long as
+    /// their operands can be lexed.
+    Ignore,
+}
+
+/// Lazily produces tokens from a lexer, stopping after the first error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexerStream {
+    lexer: Lexer,
+    finished: bool,
+}
+
+/// A saved lexer state, which `Lexer::restore` returns to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LexerCheckpoint {
+    index: usize,
+    byte_index: usize,
+    row: usize,
+    col: usize,
+    token_count: usize,
+    line_empty: bool,
+    eof_pending: bool,
+}
+
+/// Configures and creates a `Lexer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexerBuilder {
+    default_numeric: NumericType,
+    tab_width: usize,
+    keep_comments: bool,
+    emit_newlines: bool,
+    lint_indentation: bool,
+    emit_eof: bool,
+    max_identifier_len: Option<usize>,
+    max_line_length: Option<usize>,
+    radix_prefixes: Vec<(char, NumericType)>,
+    unknown_directive_policy: UnknownDirectivePolicy,
+    comment_styles: Vec<CommentStyle>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Lexer {
-    chars: Vec<char>,
     source: Source,
+    /// The text lexed in place of the source's, for lexers created from characters.
+    chars: Option<String>,
     tokens: Vec<Token>,
     index: usize,
+    byte_index: usize,
     row: usize,
     col: usize,
     default_numeric: NumericType,
+    tab_width: usize,
+    keep_comments: bool,
+    emit_newlines: bool,
+    /// Whether no token other than a newline has been produced since the last line break.
+    line_empty: bool,
+    warnings: Vec<LexerWarning>,
+    /// Whether an end of input token is still to be produced.
+    eof_pending: bool,
+    max_identifier_len: Option<usize>,
+    max_line_length: Option<usize>,
+    radix_prefixes: Vec<(char, NumericType)>,
+    unknown_directive_policy: UnknownDirectivePolicy,
+    comment_styles: Vec<CommentStyle>,
 }
 
 impl Lexer {
     pub fn tokenize(file: FilePtr) -> Result<Vec<Token>, LexerError> {
-        let mut lexer = Lexer::new_file(
-            file.contents().chars().collect(),
-            file,
-            NumericType::Unsigned,
-        );
+        let mut lexer = Lexer::from_source(Either::Left(file), NumericType::Unsigned);
+
+        lexer.process()?;
+
+        return Ok(lexer.into_tokens());
+    }
+
+    /// Tokenizes a file, interpreting numbers without a prefix as the given type.
+    pub fn tokenize_with_default(
+        file: FilePtr,
+        default_numeric: NumericType,
+    ) -> Result<Vec<Token>, LexerError> {
+        let mut lexer = Lexer::from_source(Either::Left(file), default_numeric);
 
         lexer.process()?;
 
@@ -43,9 +168,8 @@ impl Lexer {
     }
 
     pub fn tokenize_string(assembly: String) -> Result<Vec<Token>, LexerError> {
-        let mut lexer = Lexer::new_string(
-            assembly.chars().collect(),
-            Rc::new(assembly.into()),
+        let mut lexer = Lexer::from_source(
+            Either::Right(Rc::new(assembly.into())),
             NumericType::Unsigned,
         );
 
@@ -54,121 +178,356 @@ impl Lexer {
         return Ok(lexer.into_tokens());
     }
 
+    /// Lexes a file for its errors alone, continuing past each one, and returns every error
+    /// found. An empty result means the file lexes cleanly.
+    ///
+    /// After an error the rest of the offending word is skipped, up to the next whitespace.
+    pub fn validate(file: FilePtr) -> Vec<LexerError> {
+        let mut lexer = Lexer::from_source(Either::Left(file), NumericType::Unsigned);
+        let mut errors = Vec::new();
+
+        loop {
+            let index = lexer.index;
+
+            match lexer.next_token() {
+                Some(Ok(_)) => (),
+                Some(Err(e)) => {
+                    errors.push(e);
+                    lexer.recover(index);
+                }
+                None => break,
+            }
+        }
+
+        return errors;
+    }
+
+    pub fn stream(file: FilePtr) -> LexerStream {
+        return Lexer::from_source(Either::Left(file), NumericType::Unsigned).into_stream();
+    }
+
+    /// Tokenizes `src`, producing ranges in `file`, so `src` should be the text of `file`.
+    ///
+    /// When `src` is the contents of `file` itself, as returned by `FileInfo::contents`, it is
+    /// read in place. Any other text is copied once into a string rather than collected into
+    /// characters.
+    pub fn tokenize_str(src: &str, file: FilePtr) -> Result<Vec<Token>, LexerError> {
+        let contents = file.contents();
+        let in_place =
+            core::ptr::eq(src.as_ptr(), contents.as_ptr()) && src.len() == contents.len();
+
+        let mut lexer = Lexer::from_source(Either::Left(file), NumericType::Unsigned);
+
+        if !in_place {
+            lexer.chars = Some(String::from(src));
+        }
+
+        lexer.process()?;
+
+        return Ok(lexer.into_tokens());
+    }
+
+    /// Creates a lexer over `chars`, which should be the characters of `file`, producing ranges
+    /// in `file`.
+    #[deprecated(note = "copies the input, use `LexerBuilder` to lex the file in place")]
     pub fn new_file(chars: Vec<char>, file: FilePtr, default_numeric: NumericType) -> Self {
-        return Self {
-            chars,
-            source: Either::Left(file),
-            tokens: Vec::new(),
-            index: 0,
-            row: 0,
-            col: 0,
-            default_numeric,
-        };
+        let mut lexer = Lexer::from_source(Either::Left(file), default_numeric);
+        lexer.chars = Some(chars.into_iter().collect());
+
+        return lexer;
     }
 
+    /// Creates a lexer over `chars`, which should be the characters of `assembly`, producing
+    /// ranges in `assembly`.
+    #[deprecated(note = "copies the input, use `LexerBuilder` to lex the string in place")]
     pub fn new_string(
         chars: Vec<char>,
         assembly: Rc<AssemblyString>,
         default_numeric: NumericType,
     ) -> Self {
-        return Self {
-            chars,
-            source: Either::Right(assembly),
-            tokens: Vec::new(),
-            index: 0,
-            row: 0,
-            col: 0,
-            default_numeric,
-        };
+        let mut lexer = Lexer::from_source(Either::Right(assembly), default_numeric);
+        lexer.chars = Some(chars.into_iter().collect());
+
+        return lexer;
+    }
+
+    fn from_source(source: Source, default_numeric: NumericType) -> Self {
+        return LexerBuilder::new()
+            .default_numeric(default_numeric)
+            .build(source);
+    }
+
+    /// Sets the number of columns a tab character advances by, defaults to 1.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+
+        return self;
+    }
+
+    /// The type numbers without a prefix are interpreted as.
+    pub fn default_numeric(&self) -> NumericType {
+        return self.default_numeric;
     }
 
+    pub fn set_default_numeric(&mut self, default_numeric: NumericType) {
+        self.default_numeric = default_numeric;
+    }
+
+    /// Lexes the remaining input, appending every token to the output.
+    ///
+    /// Input that is empty or holds only whitespace and comments produces no tokens, apart from
+    /// comment, newline and end of input tokens when those are requested.
     pub fn process(&mut self) -> Result<(), LexerError> {
-        while let Some(c) = self.current() {
-            match c {
-                '\n' => self.increment_row(),
-                '%' => {
-                    self.increment();
+        while let Some(token) = self.next_token() {
+            self.tokens.push(token?);
+        }
 
-                    self.process_directive()?
+        return Ok(());
+    }
+
+    /// Lexes the input until a single token has been produced.
+    ///
+    /// Returns None once the end of the input has been reached, after the end of input token if
+    /// one was requested.
+    pub fn next_token(&mut self) -> Option<LexerResult<Token>> {
+        let count = self.tokens.len();
+
+        while self.tokens.len() == count {
+            let c = match self.current() {
+                Some(c) => c,
+                None if self.eof_pending => {
+                    self.eof_pending = false;
+
+                    return Some(Ok(self.token_from(TokenType::Eof, self.current_position())));
                 }
-                '"' => {
-                    self.increment();
-                    self.process_string()?
+                None => return None,
+            };
+
+            if let Err(e) = self.process_next(c) {
+                return Some(Err(e));
+            }
+
+            if self.max_line_length.is_some_and(|max| self.col > max) {
+                return Some(Err(LexerError::LineTooLong(
+                    self.current_position(),
+                    self.source.clone(),
+                )));
+            }
+        }
+
+        let token = self.tokens.pop()?;
+        self.line_empty = token.is_newline();
+
+        return Some(Ok(token));
+    }
+
+    /// Saves the current position in the input and the number of tokens produced so far.
+    pub fn checkpoint(&self) -> LexerCheckpoint {
+        return LexerCheckpoint {
+            index: self.index,
+            byte_index: self.byte_index,
+            row: self.row,
+            col: self.col,
+            token_count: self.tokens.len(),
+            line_empty: self.line_empty,
+            eof_pending: self.eof_pending,
+        };
+    }
+
+    /// Returns to a checkpoint taken from this lexer, discarding the tokens produced since.
+    ///
+    /// Lexing resumes from the checkpointed position, so the same tokens are produced again.
+    pub fn restore(&mut self, checkpoint: LexerCheckpoint) {
+        self.index = checkpoint.index;
+        self.byte_index = checkpoint.byte_index;
+        self.row = checkpoint.row;
+        self.col = checkpoint.col;
+        self.line_empty = checkpoint.line_empty;
+        self.eof_pending = checkpoint.eof_pending;
+        self.tokens.truncate(checkpoint.token_count);
+    }
+
+    pub fn into_stream(self) -> LexerStream {
+        return LexerStream {
+            lexer: self,
+            finished: false,
+        };
+    }
+
+    pub fn into_tokens(self) -> Vec<Token> {
+        return self.tokens;
+    }
+
+    /// The warnings raised for the input, only populated when enabled on the builder.
+    pub fn warnings(&self) -> &[LexerWarning] {
+        return &self.warnings;
+    }
+
+    /// Finds the lines whose leading whitespace mixes tabs and spaces.
+    fn mixed_indentation(&self) -> Vec<LexerWarning> {
+        let mut warnings = Vec::new();
+        let mut chars = self.text().chars().peekable();
+        let mut index = 0;
+        let mut row = 0;
+
+        while chars.peek().is_some() {
+            let line_start = Position::new(index, row, 0);
+            let mut tabs = false;
+            let mut spaces = false;
+
+            while let Some(c) = chars.next_if(|c| *c == '\t' || *c == ' ') {
+                tabs |= c == '\t';
+                spaces |= c == ' ';
+                index += 1;
+            }
+
+            if tabs && spaces {
+                warnings.push(LexerWarning::MixedIndentation(
+                    line_start,
+                    self.source.clone(),
+                ));
+            }
+
+            while let Some(c) = chars.next() {
+                index += 1;
+
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                    index += 1;
                 }
-                '#' => {
-                    self.increment();
 
-                    while let Some(c) = self.current() {
-                        if c == '\n' {
-                            break;
-                        } else {
-                            self.increment();
-                        }
-                    }
+                if c == '\n' || c == '\r' {
+                    row += 1;
+                    break;
                 }
-                ',' => {
-                    self.increment();
+            }
+        }
+
+        return warnings;
+    }
 
-                    self.tokens.push(self.new_token(TokenType::Comma, 1));
+    fn process_next(&mut self, c: char) -> LexerResult<()> {
+        if let Some(style) = self.comment_style() {
+            return match style {
+                CommentStyle::Line(c) => {
+                    self.process_line_comment(c.len_utf8());
+                    Ok(())
                 }
-                ':' => {
-                    self.increment();
+                CommentStyle::LineStr(open) => {
+                    self.process_line_comment(open.len());
+                    Ok(())
+                }
+                CommentStyle::Block { open, close } => self.process_block_comment(open, close),
+            };
+        }
+
+        match c {
+            '\n' | '\r' => self.process_line_break(),
+            '\t' => self.increment(),
+            '%' => {
+                let start = self.current_position();
+                self.increment();
+
+                let follows_operand = self
+                    .last_operand()
+                    .is_some_and(|t| t.lexeme().end().row() == start.row());
 
-                    self.tokens.push(self.new_token(TokenType::Colon, 1));
+                let word: String = self.text()[self.byte_index..]
+                    .chars()
+                    .take_while(|c| c.is_alphabetic() || *c == '_')
+                    .collect();
+
+                // After an operand, as in `A %B`, only a known directive name is taken as a
+                // directive, so that one-line blocks such as `%if X %error "m"` still work.
+                if !word.is_empty()
+                    && (!follows_operand || DIRECTIVE_NAMES.contains(&word.as_str()))
+                {
+                    self.process_directive()?
+                } else {
+                    self.tokens
+                        .push(self.token_from(TokenType::Operator(Operator::Remainder), start));
+                }
+            }
+            '"' => self.process_string()?,
+            '\'' => self.process_char()?,
+            '/' => self.process_operator(Operator::Divide, 1),
+            '+' => self.process_operator(Operator::Add, 1),
+            '*' => self.process_operator(Operator::Multiply, 1),
+            '&' => self.process_operator(Operator::And, 1),
+            '|' => self.process_operator(Operator::Or, 1),
+            '^' => self.process_operator(Operator::Xor, 1),
+            '<' | '>' => {
+                if self.peek() != Some(c) {
+                    return Err(LexerError::UnexpectedCharacter(
+                        c,
+                        self.current_position(),
+                        self.source.clone(),
+                    ));
                 }
-                '$' => {
-                    self.increment();
 
-                    self.process_register()?;
+                if c == '<' {
+                    self.process_operator(Operator::ShiftLeft, 2);
+                } else {
+                    self.process_operator(Operator::ShiftRight, 2);
                 }
-                '0' => {
-                    if self.peek().is_some() {
-                        match self.peek().unwrap() {
-                            'x' => {
-                                self.increment();
-                                self.increment();
-                                self.process_hex()?;
-                            }
-                            'b' => {
-                                self.increment();
-                                self.increment();
-                                self.process_binary()?;
-                            }
-                            'i' => {
-                                self.increment();
-                                self.increment();
-                                self.process_signed()?;
-                            }
-                            'u' => {
-                                self.increment();
-                                self.increment();
-                                self.process_unsigned()?;
-                            }
-                            'f' => {
-                                self.increment();
-                                self.increment();
-                                self.process_float()?;
-                            }
-                            _ => self.process_default_numeric()?,
-                        }
-                    } else {
-                        self.process_default_numeric()?;
-                    }
+            }
+            '-' => {
+                // Directly after an operand, as in `5-3`, this is a subtraction rather than a
+                // negative literal.
+                let follows_operand = self
+                    .last_operand()
+                    .is_some_and(|t| t.lexeme().end() == self.current_position());
+
+                if !follows_operand && self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.process_default_numeric()?;
+                } else {
+                    self.process_operator(Operator::Subtract, 1);
                 }
-                _ => {
-                    if c.is_whitespace() {
+            }
+            '(' => self.process_single_char(TokenType::LParen),
+            ')' => self.process_single_char(TokenType::RParen),
+            ',' => self.process_single_char(TokenType::Comma),
+            ':' => self.process_single_char(TokenType::Colon),
+            '$' => {
+                self.increment();
+
+                self.process_register()?;
+                self.process_register_range()?;
+            }
+            '.' => self.process_local_label()?,
+            '0' => {
+                let prefix = self.peek().map(|p| p.to_ascii_lowercase());
+
+                match self.radix_prefixes.iter().find(|(c, _)| Some(*c) == prefix) {
+                    Some((_, numeric)) => self.process_prefixed(*numeric)?,
+                    None if self.default_numeric != NumericType::Hex
+                        && prefix.is_some_and(|p| p.is_ascii_alphabetic()) =>
+                    {
+                        let start = self.current_position();
+
                         self.increment();
-                    } else if c.is_alphabetic() || c == '_' {
-                        self.process_identifier()?;
-                    } else if c.is_digit(10) || c == '-' {
-                        self.process_default_numeric()?;
-                    } else {
-                        return Err(LexerError::UnexpectedCharacter(
-                            c,
-                            self.current_position(),
-                            self.source.clone(),
-                        ));
+                        self.increment();
+
+                        return Err(LexerError::UnknownRadixPrefix(self.range_from(start)));
                     }
+                    None => self.process_default_numeric()?,
+                }
+            }
+            _ => {
+                if c.is_whitespace() {
+                    self.increment();
+                } else if self.default_numeric == NumericType::Hex && self.at_bare_hex_word() {
+                    self.process_hex(self.current_position())?;
+                } else if c.is_alphabetic() || c == '_' {
+                    self.process_identifier()?;
+                } else if c.is_digit(10) {
+                    self.process_default_numeric()?;
+                } else {
+                    return Err(LexerError::UnexpectedCharacter(
+                        c,
+                        self.current_position(),
+                        self.source.clone(),
+                    ));
                 }
             }
         }
@@ -176,8 +535,22 @@ impl Lexer {
         return Ok(());
     }
 
-    pub fn into_tokens(self) -> Vec<Token> {
-        return self.tokens;
+    fn process_single_char(&mut self, tp: TokenType) {
+        let start = self.current_position();
+        self.increment();
+
+        self.tokens.push(self.token_from(tp, start));
+    }
+
+    fn process_operator(&mut self, operator: Operator, len: usize) {
+        let start = self.current_position();
+
+        for _ in 0..len {
+            self.increment();
+        }
+
+        self.tokens
+            .push(self.token_from(TokenType::Operator(operator), start));
     }
 
     fn process_register(&mut self) -> LexerResult<()> {
@@ -198,12 +571,28 @@ impl Lexer {
             return end_position;
         }
 
-        if self.remaining_length() == 0 {
+        // A register name must not run into further letters or digits, as in `$spam`.
+        fn expect_register_end(s: &mut Lexer, starting_position: Position) -> LexerResult<()> {
+            let reference = s.current_position();
+            let end = consume_until_end_identifier(s);
+
+            if end != reference {
+                return Err(LexerError::InvalidRegister(TextRange::new(
+                    starting_position,
+                    end,
+                    s.source.clone(),
+                )));
+            }
+
+            return Ok(());
+        }
+
+        if self.current().is_none() {
             return Err(LexerError::ExpectedRegisterFoundEOF(
                 starting_position,
                 self.source.clone(),
             ));
-        } else if self.remaining_length() < 2 {
+        } else if self.peek().is_none() {
             return Err(LexerError::InvalidRegister(TextRange::new(
                 starting_position,
                 consume_until_end_identifier(self),
@@ -211,7 +600,29 @@ impl Lexer {
             )));
         }
 
-        if self.current() != Some('r') {
+        // Alias spellings for the stack and frame pointers.
+        let alias = match (
+            self.current_lowercase(),
+            self.peek().map(|c| c.to_ascii_lowercase()),
+        ) {
+            (Some('s'), Some('p')) => Some(Register::RSP),
+            (Some('f'), Some('p')) => Some(Register::RFP),
+            _ => None,
+        };
+
+        if let Some(reg) = alias {
+            self.increment();
+            self.increment();
+
+            expect_register_end(self, starting_position)?;
+
+            self.tokens
+                .push(self.token_from(TokenType::Register(reg), starting_position));
+
+            return Ok(());
+        }
+
+        if self.current_lowercase() != Some('r') {
             return Err(LexerError::InvalidRegister(TextRange::new(
                 starting_position,
                 consume_until_end_identifier(self),
@@ -232,15 +643,15 @@ impl Lexer {
 
                     let out;
 
-                    if self.current() == Some($f_ch) {
+                    if self.current_lowercase() == Some($f_ch) {
                         self.increment();
 
-                        out = ($f_r, 3)
+                        out = $f_r
                     } $(
-                        else if self.current() == Some($ch) {
+                        else if self.current_lowercase() == Some($ch) {
                             self.increment();
 
-                            out = ($r, 3)
+                            out = $r
                         }
                     )* else {
                         return Err(LexerError::InvalidRegister(TextRange::new(
@@ -255,7 +666,17 @@ impl Lexer {
             }
         }
 
-        let (reg, len) = match self.current().unwrap() {
+        let first = match self.current_lowercase() {
+            Some(c) => c,
+            None => {
+                return Err(LexerError::ExpectedRegisterFoundEOF(
+                    starting_position,
+                    self.source.clone(),
+                ))
+            }
+        };
+
+        let reg = match first {
             'f' => {
                 len_3_reg!('p': Register::RFP, 'l': Register::RFL)
             }
@@ -270,21 +691,24 @@ impl Lexer {
             }
             c => {
                 if c.is_digit(10) {
-                    self.increment();
+                    let mut index: usize = 0;
 
-                    let reference = self.current_position();
-                    let end = consume_until_end_identifier(self);
+                    while let Some(d) = self.current().and_then(|d| d.to_digit(10)) {
+                        index = index.saturating_mul(10).saturating_add(d as usize);
 
-                    if end != reference {
-                        return Err(LexerError::InvalidRegister(TextRange::new(
-                            starting_position,
-                            end,
-                            self.source.clone(),
-                        )));
+                        self.increment();
                     }
 
-                    let reg = Register::from(Register::R0 as u8 + c.to_digit(10).unwrap() as u8);
-                    (reg, 2)
+                    match GENERAL_REGISTERS.get(index) {
+                        Some(register) => *register,
+                        None => {
+                            return Err(LexerError::InvalidRegister(TextRange::new(
+                                starting_position,
+                                consume_until_end_identifier(self),
+                                self.source.clone(),
+                            )))
+                        }
+                    }
                 } else {
                     return Err(LexerError::InvalidRegister(TextRange::new(
                         starting_position,
@@ -295,13 +719,16 @@ impl Lexer {
             }
         };
 
+        expect_register_end(self, starting_position)?;
+
         self.tokens
-            .push(self.new_token(TokenType::Register(reg), len));
+            .push(self.token_from(TokenType::Register(reg), starting_position));
 
         return Ok(());
     }
 
     fn process_directive(&mut self) -> Result<(), LexerError> {
+        let start = self.current_position();
         let mut len = 0;
 
         while let Some(c) = self.current() {
@@ -320,10 +747,16 @@ impl Lexer {
             ));
         }
 
-        let range = self.current_range(len);
+        let range = self.range_from(start);
 
         if let Some(identifier) = TokenType::match_identifier(&range) {
             self.tokens.push(Token::new(identifier, range));
+        } else if self.unknown_directive_policy == UnknownDirectivePolicy::Ignore {
+            self.tokens.push(Token::new(TokenType::RawDirective, range));
+        } else if let Some(suggestion) = TokenType::closest_directive(&range.string()) {
+            return Err(LexerError::UnknownDirectiveWithSuggestion(
+                range, suggestion,
+            ));
         } else {
             return Err(LexerError::UnknownDirective(range));
         }
@@ -332,499 +765,3474 @@ impl Lexer {
     }
 
     fn process_string(&mut self) -> Result<(), LexerError> {
-        let mut len = 0;
+        // Skip the opening quote, the range only covers the contents.
+        self.increment();
+
+        let start = self.current_position();
+        let mut end = start;
         let mut terminated = false;
+        let mut value = String::new();
 
         while let Some(ch) = self.current() {
             if ch == '"' {
+                end = self.current_position();
                 terminated = true;
                 self.increment();
                 break;
-            } else if ch == '\n' {
+            } else if ch == '\n' || ch == '\r' {
                 break;
-            }
+            } else if ch == '\\' {
+                let escape_start = self.current_position();
 
-            len += 1;
-            self.increment();
-        }
+                self.increment();
 
-        if !terminated {
-            return Err(LexerError::UnterminatedString(self.current_range(len)));
-        }
+                let escaped = match self.current() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    Some('0') => '\0',
+                    Some('x') => {
+                        let mut code = 0;
 
-        let range = self.current_range_offset(len, 1);
+                        for _ in 0..2 {
+                            self.increment();
 
-        self.tokens.push(Token::new(TokenType::String, range));
+                            if let Some(d) = self.current().and_then(|c| c.to_digit(16)) {
+                                code = code * 16 + d;
+                            } else {
+                                return Err(LexerError::InvalidEscape(TextRange::new(
+                                    escape_start,
+                                    self.current_position(),
+                                    self.source.clone(),
+                                )));
+                            }
+                        }
 
-        return Ok(());
-    }
+                        // Strings hold UTF-8 text, where a code above 0x7F would encode to two
+                        // bytes rather than the one written.
+                        if code > 0x7F {
+                            self.increment();
 
-    fn process_identifier(&mut self) -> Result<(), LexerError> {
-        let mut len = 0;
-        let mut possible_opcode = true;
+                            return Err(LexerError::InvalidEscape(TextRange::new(
+                                escape_start,
+                                self.current_position(),
+                                self.source.clone(),
+                            )));
+                        }
 
-        while let Some(c) = self.current() {
-            if !c.is_alphabetic() && c != '_' {
-                break;
-            }
+                        char::from(code as u8)
+                    }
+                    Some('\n') | Some('\r') | None => break,
+                    Some(_) => {
+                        self.increment();
 
-            if c == '_' {
-                possible_opcode = false;
+                        return Err(LexerError::InvalidEscape(TextRange::new(
+                            escape_start,
+                            self.current_position(),
+                            self.source.clone(),
+                        )));
+                    }
+                };
+
+                value.push(escaped);
+            } else {
+                value.push(ch);
             }
 
             self.increment();
-            len += 1;
         }
 
-        if len == 0 {
-            return Err(LexerError::EmptyIdentifier(
-                self.current_position(),
-                self.source.clone(),
-            ));
+        if !terminated {
+            return Err(LexerError::UnterminatedString(self.range_from(start)));
         }
 
-        if possible_opcode {
-            let range = self.current_range(len);
-            if let Some(code) = Instruction::from_string(&range.string()) {
-                self.tokens
-                    .push(self.new_token(TokenType::Opcode(code), len));
-                return Ok(());
-            }
-        }
+        let range = TextRange::new(start, end, self.source.clone());
 
-        self.tokens.push(self.new_token(TokenType::Identifier, len));
+        self.tokens
+            .push(Token::new(TokenType::String(value.into()), range));
 
         return Ok(());
     }
 
-    fn process_hex(&mut self) -> Result<(), LexerError> {
-        let mut len = 0;
+    fn process_char(&mut self) -> Result<(), LexerError> {
+        let quote = self.current_position();
 
-        while let Some(c) = self.current() {
-            if c.is_digit(16) {
-                self.increment();
+        // Skip the opening quote, the range only covers the contents.
+        self.increment();
 
-                len += 1;
-            } else {
+        let start = self.current_position();
+        let mut end = start;
+        let mut terminated = false;
+        let mut count = 0;
+        let mut value = 0;
+
+        while let Some(ch) = self.current() {
+            if ch == '\'' {
+                end = self.current_position();
+                terminated = true;
+                self.increment();
+                break;
+            } else if ch == '\n' || ch == '\r' {
                 break;
             }
-        }
 
-        let range = self.current_range(len);
+            let mut decoded = ch;
 
-        if let Ok(n) = u64::from_str_radix(&range.string(), 16) {
-            self.tokens
-                .push(Token::new(TokenType::UnsignedIntegerLiteral(n), range));
-        } else {
-            return Err(LexerError::InvalidHexLiteral(range));
+            if ch == '\\' {
+                let escape_start = self.current_position();
+
+                self.increment();
+
+                decoded = match self.current() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('\\') => '\\',
+                    Some('\'') => '\'',
+                    Some('0') => '\0',
+                    Some('\n') | Some('\r') | None => break,
+                    Some(_) => {
+                        self.increment();
+
+                        return Err(LexerError::InvalidEscape(TextRange::new(
+                            escape_start,
+                            self.current_position(),
+                            self.source.clone(),
+                        )));
+                    }
+                };
+            }
+
+            value = decoded as u64;
+            count += 1;
+            self.increment();
+        }
+
+        if !terminated || count != 1 {
+            return Err(LexerError::InvalidCharLiteral(self.range_from(quote)));
         }
 
+        let range = TextRange::new(start, end, self.source.clone());
+
+        self.tokens
+            .push(Token::new(TokenType::UnsignedIntegerLiteral(value), range));
+
         return Ok(());
     }
 
-    fn process_binary(&mut self) -> Result<(), LexerError> {
-        let mut n: u64 = 0;
-        let mut len = 0;
+    /// The configured comment style starting at the current position, if any.
+    fn comment_style(&self) -> Option<CommentStyle> {
+        let rest = &self.text()[self.byte_index..];
+
+        return self
+            .comment_styles
+            .iter()
+            .find(|style| match style {
+                CommentStyle::Line(c) => rest.starts_with(*c),
+                CommentStyle::LineStr(open) => rest.starts_with(open),
+                CommentStyle::Block { open, .. } => rest.starts_with(open),
+            })
+            .copied();
+    }
+
+    /// Skips `len` bytes of characters on the current line.
+    fn skip(&mut self, len: usize) {
+        let end = self.byte_index + len;
+
+        while self.byte_index < end {
+            self.increment();
+        }
+    }
+
+    /// Processes a line comment whose opening marker is `open_len` bytes long.
+    fn process_line_comment(&mut self, open_len: usize) {
+        self.skip(open_len);
+
+        let starting_position = self.current_position();
 
         while let Some(c) = self.current() {
-            if c.is_digit(2) {
+            if c == '\n' || c == '\r' {
+                break;
+            } else {
                 self.increment();
+            }
+        }
 
-                if len == 64 {
-                    return Err(LexerError::InvalidBinaryLiteral(self.current_range(len)));
+        self.push_comment(starting_position);
+    }
+
+    fn process_block_comment(&mut self, open: &str, close: &str) -> Result<(), LexerError> {
+        let starting_position = self.current_position();
+
+        self.skip(open.len());
+
+        let contents_position = self.current_position();
+        let mut depth = 1;
+
+        while let Some(c) = self.current() {
+            let rest = &self.text()[self.byte_index..];
+
+            if rest.starts_with(close) {
+                if depth == 1 {
+                    self.push_comment(contents_position);
                 }
 
-                if n == 0 {
-                    n = c.to_digit(2).unwrap() as u64;
-                } else {
-                    n <<= 1;
-                    n |= c.to_digit(2).unwrap() as u64;
+                self.skip(close.len());
+
+                depth -= 1;
+
+                if depth == 0 {
+                    return Ok(());
                 }
+            } else if rest.starts_with(open) {
+                self.skip(open.len());
 
-                len += 1;
+                depth += 1;
+            } else if c == '\n' || c == '\r' {
+                self.increment_line_break();
             } else {
-                break;
+                self.increment();
             }
         }
 
-        self.tokens
-            .push(self.new_token(TokenType::UnsignedIntegerLiteral(n), len));
-
-        return Ok(());
+        return Err(LexerError::UnterminatedBlockComment(
+            starting_position,
+            self.source.clone(),
+        ));
     }
 
-    fn process_default_numeric(&mut self) -> Result<(), LexerError> {
-        return match self.default_numeric {
-            NumericType::Signed => self.process_signed(),
-            NumericType::Unsigned => {
-                if self.current() == Some('-') {
-                    return Err(LexerError::UnexpectedCharacter(
-                        '-',
-                        self.current_position(),
-                        self.source.clone(),
-                    ));
-                }
+    /// Combines the register just lexed with a following `-$rN` into a register range.
+    fn process_register_range(&mut self) -> LexerResult<()> {
+        if self.current() != Some('-') || self.peek() != Some('$') {
+            return Ok(());
+        }
 
-                self.process_unsigned()
+        self.increment();
+        self.increment();
+
+        self.process_register()?;
+
+        let end = self.tokens.pop().unwrap();
+        let start = self.tokens.pop().unwrap();
+
+        let range = TextRange::new(
+            start.lexeme().start(),
+            end.lexeme().end(),
+            self.source.clone(),
+        );
+
+        return match (start.token_type(), end.token_type()) {
+            (TokenType::Register(first), TokenType::Register(last))
+                if first as u8 >= Register::R0 as u8
+                    && last as u8 >= Register::R0 as u8
+                    && first as u8 <= last as u8 =>
+            {
+                self.tokens
+                    .push(Token::new(TokenType::RegisterRange(first, last), range));
+
+                Ok(())
             }
-            NumericType::Float => self.process_float(),
+            _ => Err(LexerError::InvalidRegisterRange(range)),
         };
     }
 
-    fn process_signed(&mut self) -> Result<(), LexerError> {
-        let mut len;
-        let mut n = 0;
-        let negative = self.current() == Some('-');
-
-        if negative {
-            self.increment();
-            len = 1;
-        } else {
-            len = 0;
+    fn process_local_label(&mut self) -> LexerResult<()> {
+        let dot_position = self.current_position();
+
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+            _ => {
+                return Err(LexerError::UnexpectedCharacter(
+                    '.',
+                    dot_position,
+                    self.source.clone(),
+                ))
+            }
         }
 
+        self.increment();
+
         while let Some(c) = self.current() {
-            if !c.is_digit(10) {
+            if !c.is_ascii_alphanumeric() && c != '_' {
                 break;
             }
 
-            if n == 0 {
-                n = c.to_digit(10).unwrap() as i64;
-            } else {
-                n *= 10;
-                if let Some(new) = n.checked_add(c.to_digit(10).unwrap() as i64) {
-                    n = new;
-                } else {
-                    return Err(LexerError::InvalidSignedIntegerLiteral(
-                        self.current_range(len + 1),
-                    ));
-                }
-            }
-
             self.increment();
-            len += 1;
-        }
-
-        if len == 0 {
-            return Err(LexerError::InvalidSignedIntegerLiteral(TextRange::new(
-                self.current_position(),
-                self.current_position(),
-                self.source.clone(),
-            )));
-        }
-
-        if negative {
-            n *= -1;
         }
 
         self.tokens
-            .push(self.new_token(TokenType::SignedIntegerLiteral(n), len));
+            .push(self.token_from(TokenType::LocalLabel, dot_position));
 
         return Ok(());
     }
 
-    fn process_unsigned(&mut self) -> Result<(), LexerError> {
+    fn process_identifier(&mut self) -> Result<(), LexerError> {
+        let start = self.current_position();
         let mut len = 0;
-        let mut n = 0;
+        let mut possible_opcode = true;
 
         while let Some(c) = self.current() {
-            if !c.is_digit(10) {
+            if !c.is_alphanumeric() && c != '_' {
                 break;
             }
 
-            if n == 0 {
-                n = c.to_digit(10).unwrap() as u64;
-            } else {
-                n *= 10;
-                if let Some(new) = n.checked_add(c.to_digit(10).unwrap() as u64) {
-                    n = new;
-                } else {
-                    return Err(LexerError::InvalidUnsignedIntegerLiteral(
-                        self.current_range(len + 1),
-                    ));
-                }
+            if !c.is_ascii() {
+                return Err(LexerError::NonAsciiIdentifier(
+                    c,
+                    self.current_position(),
+                    self.source.clone(),
+                ));
+            }
+
+            if c == '_' || c.is_digit(10) {
+                possible_opcode = false;
             }
 
             self.increment();
             len += 1;
+
+            if self.max_identifier_len.is_some_and(|max| len > max) {
+                return Err(LexerError::IdentifierTooLong(self.range_from(start)));
+            }
         }
 
         if len == 0 {
-            return Err(LexerError::InvalidSignedIntegerLiteral(TextRange::new(
-                self.current_position(),
+            return Err(LexerError::EmptyIdentifier(
                 self.current_position(),
                 self.source.clone(),
-            )));
+            ));
+        }
+
+        if possible_opcode {
+            let range = self.range_from(start);
+
+            if let Some((suffix_len, code)) = self.opcode_suffix(&range.string()) {
+                // Skip the '.' and the suffix
+                for _ in 0..=suffix_len {
+                    self.increment();
+                }
+
+                self.tokens
+                    .push(self.token_from(TokenType::Opcode(code), start));
+                return Ok(());
+            }
+
+            if let Some(code) = Instruction::from_string(&range.string()) {
+                self.tokens.push(Token::new(TokenType::Opcode(code), range));
+                return Ok(());
+            }
         }
 
         self.tokens
-            .push(self.new_token(TokenType::UnsignedIntegerLiteral(n), len));
+            .push(self.token_from(TokenType::Identifier, start));
 
         return Ok(());
     }
 
-    fn process_float(&mut self) -> Result<(), LexerError> {
-        let mut len;
-        let mut found_point = false;
+    /// Lexes hex digits following a prefix that started at `prefix_start`.
+    ///
+    /// A leading '-', as in `0x-1`, produces a signed literal holding the negated magnitude,
+    /// which must fit in an i64.
+    fn process_hex(&mut self, prefix_start: Position) -> Result<(), LexerError> {
+        let start = self.current_position();
+        let negative = self.current() == Some('-');
+        let mut len = 0;
 
-        if self.current() == Some('-') {
-            len = 1;
+        if negative {
             self.increment();
-        } else {
-            len = 0;
         }
 
         while let Some(c) = self.current() {
-            if !c.is_digit(10) {
-                if c == '.' && found_point || c != '.' {
-                    break;
-                } else {
-                    found_point = true;
-                }
-            }
+            if c.is_digit(16) {
+                self.increment();
 
-            self.increment();
-            len += 1;
+                len += 1;
+            } else {
+                break;
+            }
         }
 
         if len == 0 {
-            return Err(LexerError::InvalidFloatLiteral(TextRange::new(
-                self.current_position(),
-                self.current_position(),
-                self.source.clone(),
-            )));
+            // Point at the prefix when no digits follow it.
+            return Err(LexerError::InvalidHexLiteral(self.range_from(prefix_start)));
         }
 
-        let range = self.current_range(len);
+        let range = self.range_from(start);
 
-        if let Ok(f) = fast_float::parse(range.string()) {
+        if len > MAX_HEX_DIGITS {
+            return Err(LexerError::InvalidHexLiteral(range));
+        }
+
+        if negative {
+            if let Ok(n) = i64::from_str_radix(&range.string(), 16) {
+                self.tokens
+                    .push(Token::new(TokenType::SignedIntegerLiteral(n), range));
+            } else {
+                return Err(LexerError::InvalidHexLiteral(range));
+            }
+        } else if let Ok(n) = u64::from_str_radix(&range.string(), 16) {
             self.tokens
-                .push(Token::new(TokenType::FloatLiteral(f), range));
+                .push(Token::new(TokenType::UnsignedIntegerLiteral(n), range));
         } else {
-            return Err(LexerError::InvalidFloatLiteral(range));
+            return Err(LexerError::InvalidHexLiteral(range));
         }
 
         return Ok(());
     }
 
-    fn current(&self) -> Option<char> {
-        if self.index < self.chars.len() {
-            return Some(self.chars[self.index]);
-        } else {
-            return None;
+    /// Lexes binary digits following a prefix that started at `prefix_start`.
+    ///
+    /// A leading '-' is handled as for hex literals.
+    fn process_binary(&mut self, prefix_start: Position) -> Result<(), LexerError> {
+        let start = self.current_position();
+        let negative = self.current() == Some('-');
+        let mut n: u64 = 0;
+        let mut len = 0;
+
+        if negative {
+            self.increment();
         }
-    }
 
-    fn peek(&self) -> Option<char> {
-        if self.index + 1 < self.chars.len() {
-            return Some(self.chars[self.index + 1]);
-        } else {
-            return None;
+        while let Some(c) = self.current() {
+            if c.is_digit(2) {
+                self.increment();
+
+                if len == 64 {
+                    return Err(LexerError::InvalidBinaryLiteral(self.range_from(start)));
+                }
+
+                n = (n << 1) | c.to_digit(2).unwrap() as u64;
+
+                len += 1;
+            } else {
+                break;
+            }
         }
-    }
 
-    fn increment(&mut self) {
-        self.index += 1;
-        self.col += 1;
-    }
+        if len == 0 {
+            // Point at the prefix when no digits follow it.
+            return Err(LexerError::InvalidBinaryLiteral(
+                self.range_from(prefix_start),
+            ));
+        }
 
-    fn increment_row(&mut self) {
-        self.index += 1;
-        self.col = 0;
-        self.row += 1;
-    }
+        if negative {
+            // The magnitude of i64::MIN is the largest that can be negated.
+            if n > i64::MIN.unsigned_abs() {
+                return Err(LexerError::InvalidBinaryLiteral(self.range_from(start)));
+            }
 
-    fn new_token(&self, tp: TokenType, lexeme_len: usize) -> Token {
-        return Token::new(tp, self.current_range(lexeme_len));
-    }
+            self.tokens.push(self.token_from(
+                TokenType::SignedIntegerLiteral((n as i64).wrapping_neg()),
+                start,
+            ));
+        } else {
+            self.tokens
+                .push(self.token_from(TokenType::UnsignedIntegerLiteral(n), start));
+        }
 
-    fn current_range(&self, lexeme_len: usize) -> TextRange {
-        return self.current_range_offset(lexeme_len, 0);
+        return Ok(());
     }
 
-    fn current_range_offset(&self, lexeme_len: usize, offset: usize) -> TextRange {
-        return TextRange::new(
-            Position::new(
-                self.index - lexeme_len - offset,
-                self.row,
-                self.col - lexeme_len - offset,
-            ),
-            Position::new(self.index - offset, self.row, self.col - offset),
-            self.source.clone(),
-        );
-    }
+    /// Processes a literal starting with `0` and a radix prefix, as in `0x1F`.
+    fn process_prefixed(&mut self, numeric: NumericType) -> LexerResult<()> {
+        let prefix_start = self.current_position();
 
-    fn current_position(&self) -> Position {
-        return Position::new(self.index, self.row, self.col);
-    }
+        self.increment();
+        self.increment();
 
-    fn remaining_length(&self) -> usize {
-        return self.chars.len() - self.index;
+        return match numeric {
+            NumericType::Hex => self.process_hex(prefix_start),
+            NumericType::Bin => self.process_binary(prefix_start),
+            NumericType::Signed => {
+                self.process_signed()?;
+                self.process_width_annotation(true)
+            }
+            NumericType::Unsigned => {
+                self.process_unsigned()?;
+                self.process_width_annotation(false)
+            }
+            NumericType::Float => self.process_float(),
+        };
     }
-}
+
+    fn process_default_numeric(&mut self) -> Result<(), LexerError> {
+        return match self.default_numeric {
+            NumericType::Signed => self.process_signed(),
+            NumericType::Unsigned => {
+                if self.current() == Some('-') {
+                    return Err(LexerError::UnexpectedCharacter(
+                        '-',
+                        self.current_position(),
+                        self.source.clone(),
+                    ));
+                }
+
+                self.process_unsigned()
+            }
+            NumericType::Float => self.process_float(),
+            NumericType::Hex | NumericType::Bin => {
+                match self.current() {
+                    Some(c) if c.is_digit(self.default_radix()) => (),
+                    c => {
+                        return Err(LexerError::UnexpectedCharacter(
+                            c.unwrap_or('\0'),
+                            self.current_position(),
+                            self.source.clone(),
+                        ))
+                    }
+                }
+
+                let start = self.current_position();
+
+                if self.default_numeric == NumericType::Hex {
+                    self.process_hex(start)
+                } else {
+                    self.process_binary(start)
+                }
+            }
+        };
+    }
+
+    fn default_radix(&self) -> u32 {
+        return match self.default_numeric {
+            NumericType::Hex => 16,
+            NumericType::Bin => 2,
+            _ => 10,
+        };
+    }
+
+    /// The length and opcode of a `.suffix` at the current position that, appended to the
+    /// mnemonic, names a width variant such as `ld.b`.
+    fn opcode_suffix(&self, mnemonic: &str) -> Option<(usize, u8)> {
+        if self.current() != Some('.') {
+            return None;
+        }
+
+        let suffix: String = self.text()[self.byte_index + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+
+        if suffix.is_empty() {
+            return None;
+        }
+
+        let code = Instruction::from_string(&format!("{}.{}", mnemonic, suffix))?;
+
+        return Some((suffix.len(), code));
+    }
+
+    /// Whether the word at the current position is made up only of hex digits and is not an
+    /// opcode, in which case the hex default reads it as a number.
+    fn at_bare_hex_word(&self) -> bool {
+        let word: String = self.text()[self.byte_index..]
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+
+        return word.chars().all(|c| c.is_ascii_hexdigit())
+            && Instruction::from_string(&word).is_none();
+    }
+
+    fn process_signed(&mut self) -> Result<(), LexerError> {
+        let start = self.current_position();
+        let mut digits = 0;
+        let mut n: i64 = 0;
+        let mut overflowed = false;
+        let negative = self.current() == Some('-');
+
+        if negative {
+            self.increment();
+        }
+
+        while let Some(c) = self.current() {
+            if !c.is_digit(10) {
+                break;
+            }
+
+            let digit = c.to_digit(10).unwrap() as i64;
+
+            // Negative values are accumulated directly so that i64::MIN can be represented.
+            if let Some(new) = n.checked_mul(10).and_then(|v| {
+                if negative {
+                    v.checked_sub(digit)
+                } else {
+                    v.checked_add(digit)
+                }
+            }) {
+                n = new;
+            } else {
+                overflowed = true;
+            }
+
+            self.increment();
+            digits += 1;
+        }
+
+        if overflowed {
+            return Err(LexerError::InvalidSignedIntegerLiteral(
+                self.range_from(start),
+            ));
+        }
+
+        if digits == 0 {
+            return Err(LexerError::InvalidSignedIntegerLiteral(TextRange::new(
+                self.current_position(),
+                self.current_position(),
+                self.source.clone(),
+            )));
+        }
+
+        self.tokens
+            .push(self.token_from(TokenType::SignedIntegerLiteral(n), start));
+
+        return Ok(());
+    }
+
+    /// Handles a width annotation such as `0u8:255`, where the literal just lexed is the width.
+    ///
+    /// The width token is replaced by the value following the colon, which must fit in the width
+    /// and carries it.
+    fn process_width_annotation(&mut self, signed: bool) -> LexerResult<()> {
+        let value_follows = match self.peek() {
+            Some(c) => c.is_digit(10) || (signed && c == '-'),
+            None => false,
+        };
+
+        if self.current() != Some(':') || !value_follows {
+            return Ok(());
+        }
+
+        let width = match self.tokens.last().unwrap().token_type() {
+            TokenType::UnsignedIntegerLiteral(w) => w,
+            TokenType::SignedIntegerLiteral(w) => w as u64,
+            _ => return Ok(()),
+        };
+
+        if !INTEGER_WIDTHS.contains(&width) {
+            return Ok(());
+        }
+
+        self.tokens.pop();
+        self.increment();
+
+        if signed {
+            self.process_signed()?;
+        } else {
+            self.process_unsigned()?;
+        }
+
+        let value = self.tokens.last().unwrap();
+
+        let fits = match value.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) => width == 64 || v < 1 << width,
+            TokenType::SignedIntegerLiteral(v) => {
+                width == 64 || (v >= -(1 << (width - 1)) && v < 1 << (width - 1))
+            }
+            _ => true,
+        };
+
+        if !fits {
+            let range = value.lexeme().clone();
+
+            if signed {
+                return Err(LexerError::InvalidSignedIntegerLiteral(range));
+            } else {
+                return Err(LexerError::InvalidUnsignedIntegerLiteral(range));
+            }
+        }
+
+        let value = self.tokens.pop().unwrap().with_width(width as u8);
+        self.tokens.push(value);
+
+        return Ok(());
+    }
+
+    fn process_unsigned(&mut self) -> Result<(), LexerError> {
+        let start = self.current_position();
+        let mut len = 0;
+        let mut n: u64 = 0;
+        let mut overflowed = false;
+
+        while let Some(c) = self.current() {
+            if !c.is_digit(10) {
+                break;
+            }
+
+            if let Some(new) = n
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(c.to_digit(10).unwrap() as u64))
+            {
+                n = new;
+            } else {
+                overflowed = true;
+            }
+
+            self.increment();
+            len += 1;
+        }
+
+        if overflowed {
+            return Err(LexerError::InvalidUnsignedIntegerLiteral(
+                self.range_from(start),
+            ));
+        }
+
+        if len == 0 {
+            return Err(LexerError::InvalidUnsignedIntegerLiteral(TextRange::new(
+                self.current_position(),
+                self.current_position(),
+                self.source.clone(),
+            )));
+        }
+
+        self.tokens
+            .push(self.token_from(TokenType::UnsignedIntegerLiteral(n), start));
+
+        return Ok(());
+    }
+
+    /// Lexes a float written as the hex digits of its IEEE-754 bit pattern, as in
+    /// `0f0x400921FB54442D18`. Exactly 16 digits are required.
+    fn process_float_bits(&mut self) -> Result<(), LexerError> {
+        let start = self.current_position();
+
+        // Skip the "0x"
+        self.increment();
+        self.increment();
+
+        let digits_start = self.current_position();
+        let mut len = 0;
+
+        while let Some(c) = self.current() {
+            if !c.is_digit(16) {
+                break;
+            }
+
+            self.increment();
+            len += 1;
+        }
+
+        let range = self.range_from(start);
+
+        if len != MAX_HEX_DIGITS {
+            return Err(LexerError::InvalidFloatLiteral(range));
+        }
+
+        let bits = match u64::from_str_radix(&self.range_from(digits_start).string(), 16) {
+            Ok(bits) => bits,
+            Err(_) => return Err(LexerError::InvalidFloatLiteral(range)),
+        };
+
+        self.tokens.push(Token::new(
+            TokenType::FloatLiteral(f64::from_bits(bits)),
+            range,
+        ));
+
+        return Ok(());
+    }
+
+    fn process_float(&mut self) -> Result<(), LexerError> {
+        if self.current() == Some('0') && matches!(self.peek(), Some('x' | 'X')) {
+            return self.process_float_bits();
+        }
+
+        let start = self.current_position();
+        let mut len;
+        let mut found_point = false;
+
+        if self.current() == Some('-') {
+            len = 1;
+            self.increment();
+        } else {
+            len = 0;
+        }
+
+        while let Some(c) = self.current() {
+            if c == '.' {
+                if found_point {
+                    return Err(LexerError::UnexpectedSecondDecimalPoint(
+                        self.current_position(),
+                        self.source.clone(),
+                    ));
+                }
+
+                found_point = true;
+            } else if !c.is_digit(10) {
+                break;
+            }
+
+            self.increment();
+            len += 1;
+        }
+
+        if self.current() == Some('e') || self.current() == Some('E') {
+            self.increment();
+            len += 1;
+
+            if self.current() == Some('-') || self.current() == Some('+') {
+                self.increment();
+                len += 1;
+            }
+
+            let mut exponent_digits = 0;
+
+            while let Some(c) = self.current() {
+                if !c.is_digit(10) {
+                    break;
+                }
+
+                self.increment();
+                len += 1;
+                exponent_digits += 1;
+            }
+
+            if exponent_digits == 0 {
+                return Err(LexerError::InvalidFloatLiteral(self.range_from(start)));
+            }
+        }
+
+        if len == 0 {
+            return Err(LexerError::InvalidFloatLiteral(TextRange::new(
+                self.current_position(),
+                self.current_position(),
+                self.source.clone(),
+            )));
+        }
+
+        let range = self.range_from(start);
+
+        if let Ok(f) = fast_float::parse(range.string()) {
+            self.tokens
+                .push(Token::new(TokenType::FloatLiteral(f), range));
+        } else {
+            return Err(LexerError::InvalidFloatLiteral(range));
+        }
+
+        return Ok(());
+    }
+
+    /// Skips to the next whitespace after an error, making sure that at least one character has
+    /// been consumed since `index`.
+    fn recover(&mut self, index: usize) {
+        if self.index == index {
+            self.increment();
+        }
+
+        while let Some(c) = self.current() {
+            if c.is_whitespace() {
+                break;
+            }
+
+            self.increment();
+        }
+    }
+
+    /// The text being lexed, read directly from the source to avoid copying it unless the lexer
+    /// was created from characters.
+    fn text(&self) -> &str {
+        if let Some(chars) = &self.chars {
+            return chars;
+        }
+
+        return match &self.source {
+            Either::Left(f) => f.contents(),
+            Either::Right(s) => s.as_str(),
+        };
+    }
+
+    fn current(&self) -> Option<char> {
+        return self.text()[self.byte_index..].chars().next();
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.text()[self.byte_index..].chars().nth(1);
+    }
+
+    /// The current character folded to lowercase, for case-insensitive matching.
+    fn current_lowercase(&self) -> Option<char> {
+        return self.current().map(|c| c.to_ascii_lowercase());
+    }
+
+    /// Moves past the current character without updating the row or column.
+    fn advance(&mut self) {
+        if let Some(c) = self.current() {
+            self.byte_index += c.len_utf8();
+        }
+
+        self.index += 1;
+    }
+
+    /// Moves past the current character, which must not be a line break.
+    fn increment(&mut self) {
+        if self.current() == Some('\t') {
+            self.increment_tab();
+        } else {
+            self.advance();
+            self.col += 1;
+        }
+    }
+
+    fn increment_tab(&mut self) {
+        self.advance();
+        self.col += self.tab_width;
+    }
+
+    /// Moves past a line break between statements, producing a newline token if requested.
+    ///
+    /// Blank lines, and line breaks before the first token, produce nothing.
+    fn process_line_break(&mut self) {
+        let start = self.current_position();
+
+        self.increment_line_break();
+
+        if self.emit_newlines && !self.line_empty {
+            self.tokens.push(self.token_from(TokenType::Newline, start));
+        }
+    }
+
+    /// Moves past a line break, treating "\r\n" as a single line break.
+    fn increment_line_break(&mut self) {
+        if self.current() == Some('\r') && self.peek() == Some('\n') {
+            self.advance();
+        }
+
+        self.increment_row();
+    }
+
+    fn increment_row(&mut self) {
+        self.advance();
+        self.col = 0;
+        self.row += 1;
+    }
+
+    /// Pushes a comment token from the given position up to the current one, if comments are kept.
+    fn push_comment(&mut self, starting_position: Position) {
+        if self.keep_comments {
+            let range = TextRange::new(
+                starting_position,
+                self.current_position(),
+                self.source.clone(),
+            );
+
+            self.tokens.push(Token::new(TokenType::Comment, range));
+        }
+    }
+
+    /// A token covering everything consumed since `start`.
+    fn token_from(&self, tp: TokenType, start: Position) -> Token {
+        return Token::new(tp, self.range_from(start));
+    }
+
+    /// The range from `start` up to the current position.
+    fn range_from(&self, start: Position) -> TextRange {
+        return TextRange::new(start, self.current_position(), self.source.clone());
+    }
+
+    fn current_position(&self) -> Position {
+        return Position::new(self.index, self.row, self.col);
+    }
+
+    /// The last token lexed if it can end an operand, so that a `-` or `%` following it is a
+    /// binary operator.
+    fn last_operand(&self) -> Option<&Token> {
+        return self.tokens.last().filter(|t| match t.token_type() {
+            TokenType::UnsignedIntegerLiteral(_)
+            | TokenType::SignedIntegerLiteral(_)
+            | TokenType::FloatLiteral(_)
+            | TokenType::Identifier
+            | TokenType::LocalLabel
+            | TokenType::RParen => true,
+            _ => false,
+        });
+    }
+}
+
+/// Panics unless `c` can start a line comment without clashing with other syntax.
+fn assert_comment_char(c: char) {
+    assert!(
+        !RESERVED_CHARS.contains(&c) && !c.is_alphanumeric() && !c.is_whitespace(),
+        "'{}' cannot be used as the comment character",
+        c
+    );
+}
+
+impl LexerBuilder {
+    pub fn new() -> Self {
+        return Self {
+            default_numeric: NumericType::Unsigned,
+            tab_width: 1,
+            keep_comments: false,
+            emit_newlines: false,
+            lint_indentation: false,
+            emit_eof: false,
+            max_identifier_len: None,
+            max_line_length: None,
+            radix_prefixes: DEFAULT_RADIX_PREFIXES.to_vec(),
+            unknown_directive_policy: UnknownDirectivePolicy::Error,
+            comment_styles: DEFAULT_COMMENT_STYLES.to_vec(),
+        };
+    }
+
+    /// Sets the type numbers without a prefix are interpreted as, defaults to unsigned.
+    pub fn default_numeric(mut self, default_numeric: NumericType) -> Self {
+        self.default_numeric = default_numeric;
+
+        return self;
+    }
+
+    /// Sets the number of columns a tab character advances by, defaults to 1.
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+
+        return self;
+    }
+
+    /// Sets whether comments are produced as tokens, defaults to false.
+    pub fn keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+
+        return self;
+    }
+
+    /// Sets whether line breaks are produced as newline tokens, defaults to false.
+    ///
+    /// Consecutive line breaks produce a single token.
+    pub fn emit_newlines(mut self, emit_newlines: bool) -> Self {
+        self.emit_newlines = emit_newlines;
+
+        return self;
+    }
+
+    /// Sets whether lines indented with both tabs and spaces are reported as warnings, defaults
+    /// to false.
+    pub fn lint_indentation(mut self, lint_indentation: bool) -> Self {
+        self.lint_indentation = lint_indentation;
+
+        return self;
+    }
+
+    /// Sets whether an end of input token is produced after the last token, defaults to false.
+    ///
+    /// The token has an empty range at the final position.
+    pub fn emit_eof(mut self, emit_eof: bool) -> Self {
+        self.emit_eof = emit_eof;
+
+        return self;
+    }
+
+    /// Sets the longest identifier accepted, in characters, defaults to no limit.
+    pub fn max_identifier_len(mut self, max_identifier_len: usize) -> Self {
+        self.max_identifier_len = Some(max_identifier_len);
+
+        return self;
+    }
+
+    /// Sets the most columns a line may take up, defaults to no limit.
+    ///
+    /// Lexing stops with an error at the end of the first token to pass the limit.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = Some(max_line_length);
+
+        return self;
+    }
+
+    /// Sets the characters that may follow a `0` to give the type of a literal, defaults to
+    /// `DEFAULT_RADIX_PREFIXES`.
+    ///
+    /// Prefixes are matched case-insensitively. Leaving one out disallows that form of literal,
+    /// so removing `f` rejects `0f1.5`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a prefix is not an ASCII letter.
+    pub fn radix_prefixes(mut self, radix_prefixes: Vec<(char, NumericType)>) -> Self {
+        self.radix_prefixes = radix_prefixes
+            .into_iter()
+            .map(|(c, numeric)| {
+                assert!(
+                    c.is_ascii_alphabetic(),
+                    "'{}' cannot be used as a radix prefix",
+                    c
+                );
+
+                (c.to_ascii_lowercase(), numeric)
+            })
+            .collect();
+
+        return self;
+    }
+
+    /// Sets what happens to directives the lexer does not know, defaults to
+    /// `UnknownDirectivePolicy::Error`.
+    pub fn unknown_directive_policy(mut self, policy: UnknownDirectivePolicy) -> Self {
+        self.unknown_directive_policy = policy;
+
+        return self;
+    }
+
+    /// Sets the character that starts a line comment, defaults to '#'.
+    ///
+    /// This replaces any line comment characters set with `comment_styles`, keeping the other
+    /// styles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the character already has a meaning to the lexer, such as '$', ',', ':' or '%',
+    /// or could start a word, number or whitespace.
+    pub fn comment_char(mut self, comment_char: char) -> Self {
+        assert_comment_char(comment_char);
+
+        self.comment_styles
+            .retain(|style| !matches!(style, CommentStyle::Line(_)));
+        self.comment_styles
+            .insert(0, CommentStyle::Line(comment_char));
+
+        return self;
+    }
+
+    /// Sets every comment style accepted, defaults to `#` line comments and `/* */` block
+    /// comments.
+    ///
+    /// Styles are tried in order, so a style should come before any other whose opening it
+    /// starts with. A multi-character marker takes precedence over the operators it is made of,
+    /// so `//` as a line comment means `/` cannot be followed directly by another `/`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a line comment character is not allowed by `comment_char`, or if a marker is
+    /// empty.
+    pub fn comment_styles(mut self, comment_styles: Vec<CommentStyle>) -> Self {
+        for style in &comment_styles {
+            match style {
+                CommentStyle::Line(c) => assert_comment_char(*c),
+                CommentStyle::LineStr(open) => {
+                    assert!(!open.is_empty(), "comment markers cannot be empty")
+                }
+                CommentStyle::Block { open, close } => assert!(
+                    !open.is_empty() && !close.is_empty(),
+                    "comment markers cannot be empty"
+                ),
+            }
+        }
+
+        self.comment_styles = comment_styles;
+
+        return self;
+    }
+
+    /// Creates a lexer for the given file or string.
+    pub fn build(self, source: Source) -> Lexer {
+        let len = match &source {
+            Either::Left(file) => file.contents().len(),
+            Either::Right(assembly) => assembly.as_str().len(),
+        };
+
+        let mut lexer = Lexer {
+            tokens: Vec::with_capacity(len / TOKEN_CAPACITY_RATIO),
+            source,
+            chars: None,
+            index: 0,
+            byte_index: 0,
+            row: 0,
+            col: 0,
+            default_numeric: self.default_numeric,
+            tab_width: self.tab_width,
+            keep_comments: self.keep_comments,
+            emit_newlines: self.emit_newlines,
+            line_empty: true,
+            warnings: Vec::new(),
+            eof_pending: self.emit_eof,
+            max_identifier_len: self.max_identifier_len,
+            max_line_length: self.max_line_length,
+            radix_prefixes: self.radix_prefixes,
+            unknown_directive_policy: self.unknown_directive_policy,
+            comment_styles: self.comment_styles,
+        };
+
+        if self.lint_indentation {
+            lexer.warnings = lexer.mixed_indentation();
+        }
+
+        return lexer;
+    }
+}
+
+impl Default for LexerBuilder {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Iterator for LexerStream {
+    type Item = LexerResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let next = self.lexer.next_token();
+
+        if !matches!(next, Some(Ok(_))) {
+            self.finished = true;
+        }
+
+        return next;
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use crate::text_mapping::FileInfoManager;
+    use crate::token::strip_trivia;
+
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
+    macro_rules! new_token {
+        ($tp:expr, $col:expr, $len:expr, $file:expr) => {
+            Token::new(
+                $tp,
+                TextRange::new(
+                    Position::new($col, 0, $col),
+                    Position::new($col + $len, 0, $col + $len),
+                    $file,
+                ),
+            )
+        };
+
+        ($tp:expr, $index:expr, $row:expr, $col:expr, $len:expr, $file:expr) => {
+            Token::new(
+                $tp,
+                TextRange::new(
+                    Position::new($index, $row, $col),
+                    Position::new($index + $len, $row, $col + $len),
+                    $file,
+                ),
+            )
+        };
+    }
+
+    #[test]
+    fn test_registers() {
+        let input = "$rsp $rfp $rou $rfl $rra $rrb $r0 $r1 $r2 $r3 $r4 $r5 $r6 $r7 $r8 $r9";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        for i in 0..16 {
+            assert_eq!(
+                output[i],
+                new_token!(
+                    TokenType::Register(Register::from(i as u8)),
+                    1 + if i >= 6 { 5 * 6 + 4 * (i - 6) } else { 5 * i },
+                    if i >= 6 { 2 } else { 3 },
+                    Either::Left(f.clone())
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_register_r10_invalid() {
+        let input = "$r10";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidRegister(TextRange::new(
+                Position::new(1, 0, 1),
+                Position::new(4, 0, 4),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_general_register_bounds() {
+        let mut f_man = FileInfoManager::new();
+        let last = GENERAL_REGISTERS.len() - 1;
+
+        let f = f_man.new_file(String::new(), format!("$r{}", last));
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap()[0].token_type(),
+            TokenType::Register(Register::R9)
+        );
+
+        let input = format!("$r{}", last + 1);
+        let f = f_man.new_file(String::new(), input.clone());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()),
+            Err(LexerError::InvalidRegister(TextRange::new(
+                Position::new(1, 0, 1),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            )))
+        );
+    }
+
+    #[test]
+    fn test_malformed_registers() {
+        let mut f_man = FileInfoManager::new();
+
+        let mut invalid_register = |input: &str, end: usize| {
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            assert_eq!(
+                Lexer::tokenize(f.clone()),
+                Err(LexerError::InvalidRegister(TextRange::new(
+                    Position::new(1, 0, 1),
+                    Position::new(end, 0, end),
+                    Either::Left(f.clone())
+                ))),
+                "{}",
+                input
+            );
+        };
+
+        invalid_register("$x", 2);
+        invalid_register("$r", 2);
+        invalid_register("$r9extra", 8);
+        invalid_register("$rz", 3);
+        invalid_register("$spam", 5);
+        invalid_register("$SPx", 4);
+        invalid_register("$fp1", 4);
+        invalid_register("$rspx", 5);
+        invalid_register("$rfl2", 5);
+        invalid_register("$RRAb", 5);
+
+        let f = f_man.new_file(String::new(), "$".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()),
+            Err(LexerError::ExpectedRegisterFoundEOF(
+                Position::new(1, 0, 1),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_truncated_registers() {
+        let input = "$r0 $rf $rfl $sp $r9-$r0 $é $r99999999999999999999999 $-$";
+        let mut f_man = FileInfoManager::new();
+
+        for (end, _) in input.char_indices() {
+            let f = f_man.new_file(String::new(), input[..end].to_string());
+
+            // Only checks that no prefix panics
+            let _ = Lexer::tokenize(f);
+        }
+    }
+
+    #[test]
+    fn test_error_position_one_based() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::from("root.vsm"), "@".to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert!(output.to_string().contains(" at 1:1"));
+    }
+
+    #[test]
+    fn test_register_aliases() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "$sp, $rsp, $fp, $rfp".to_string());
+
+        let output: Vec<TokenType> = Lexer::tokenize(f.clone())
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.is_register())
+            .map(|t| t.token_type())
+            .collect();
+
+        assert_eq!(
+            output,
+            vec![
+                TokenType::Register(Register::RSP),
+                TokenType::Register(Register::RSP),
+                TokenType::Register(Register::RFP),
+                TokenType::Register(Register::RFP),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_register_case_insensitive() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "$RSP, $R0, $Rfp, $SP".to_string());
+
+        let output: Vec<TokenType> = Lexer::tokenize(f.clone())
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.is_register())
+            .map(|t| t.token_type())
+            .collect();
+
+        assert_eq!(
+            output,
+            vec![
+                TokenType::Register(Register::RSP),
+                TokenType::Register(Register::R0),
+                TokenType::Register(Register::RFP),
+                TokenType::Register(Register::RSP),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_identifier() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "main".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::Identifier,
+                0,
+                4,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let f = f_man.new_file(String::new(), "café".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::NonAsciiIdentifier('é', Position::new(3, 0, 3), Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_local_label() {
+        let input = ".loop:\njmp .loop";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::LocalLabel, 0, 5, Either::Left(f.clone())),
+                new_token!(TokenType::Colon, 5, 1, Either::Left(f.clone())),
+                new_token!(TokenType::Opcode(0x37), 7, 1, 0, 3, Either::Left(f.clone())),
+                new_token!(TokenType::LocalLabel, 11, 1, 4, 5, Either::Left(f.clone())),
+            ]
+        );
+
+        let f = f_man.new_file(String::new(), "jmp .".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::UnexpectedCharacter('.', Position::new(4, 0, 4), Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_tokenize_with_default() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "-5".to_string());
+
+        assert_eq!(
+            Lexer::tokenize_with_default(f.clone(), NumericType::Signed).unwrap(),
+            vec![new_token!(
+                TokenType::SignedIntegerLiteral(-5),
+                0,
+                2,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let mut lexer = Lexer::from_source(Either::Left(f), NumericType::Unsigned);
+        lexer.set_default_numeric(NumericType::Float);
+
+        assert_eq!(lexer.default_numeric(), NumericType::Float);
+    }
+
+    #[test]
+    fn test_column_after_line_break() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "halt\nfoo\r\nbar".to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(output[1].lexeme().start(), Position::new(5, 1, 0));
+        assert_eq!(output[1].lexeme().end(), Position::new(8, 1, 3));
+        assert_eq!(output[2].lexeme().start().row(), 2);
+        assert_eq!(output[2].lexeme().start().col(), 0);
+    }
+
+    #[test]
+    fn test_escape_spans() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "\"a\\x41\\n\" '\\t'".to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(output[0].token_type(), TokenType::String("aA\n".into()));
+        assert_eq!(output[0].lexeme().start(), Position::new(1, 0, 1));
+        assert_eq!(output[0].lexeme().end(), Position::new(8, 0, 8));
+        assert_eq!(output[0].lexeme().string(), "a\\x41\\n");
+
+        assert_eq!(output[1].token_type(), TokenType::UnsignedIntegerLiteral(9));
+        assert_eq!(output[1].lexeme().string(), "\\t");
+
+        let f = f_man.new_file(String::new(), "'ab'".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidCharLiteral(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(4, 0, 4),
+                Either::Left(f)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_opcode_whole_word() {
+        let mut f_man = FileInfoManager::new();
+
+        for input in ["callee", "call_site", "calling", "calln", "call2"] {
+            let f = f_man.new_file(String::new(), input.to_string());
+            let output = Lexer::tokenize(f).unwrap();
+
+            assert_eq!(output.len(), 1);
+            assert_eq!(output[0].token_type(), TokenType::Identifier, "{}", input);
+        }
+
+        let f = f_man.new_file(String::new(), "call".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f).unwrap()[0].token_type(),
+            TokenType::Opcode(0x43)
+        );
+    }
+
+    #[test]
+    fn test_opcode_suffix() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "halt.b".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![
+                new_token!(TokenType::Opcode(0x45), 0, 4, Either::Left(f.clone())),
+                new_token!(TokenType::LocalLabel, 4, 2, Either::Left(f.clone())),
+            ]
+        );
+
+        let f = f_man.new_file(String::new(), "ld_1.b".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap()[0].token_type(),
+            TokenType::Identifier
+        );
+    }
+
+    #[test]
+    fn test_max_identifier_len() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "abcd".to_string());
+        let mut lexer = LexerBuilder::new()
+            .max_identifier_len(4)
+            .build(Either::Left(f.clone()));
+
+        assert!(lexer.process().is_ok());
+
+        let f = f_man.new_file(String::new(), "abcdefgh".to_string());
+        let mut lexer = LexerBuilder::new()
+            .max_identifier_len(4)
+            .build(Either::Left(f.clone()));
+
+        assert_eq!(
+            lexer.process(),
+            Err(LexerError::IdentifierTooLong(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(5, 0, 5),
+                Either::Left(f.clone())
+            )))
+        );
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "halt\nabcdefghij".to_string());
+        let mut lexer = LexerBuilder::new()
+            .max_line_length(10)
+            .build(Either::Left(f.clone()));
+
+        assert!(lexer.process().is_ok());
+
+        let f = f_man.new_file(String::new(), "halt\nabcdefghijklmno".to_string());
+        let mut lexer = LexerBuilder::new()
+            .max_line_length(10)
+            .build(Either::Left(f.clone()));
+
+        assert_eq!(
+            lexer.process(),
+            Err(LexerError::LineTooLong(
+                Position::new(20, 1, 15),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_hex_default() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "ldi $r0, ff\nldi $r1, 1F\nhalt".to_string());
+
+        let output = Lexer::tokenize_with_default(f.clone(), NumericType::Hex).unwrap();
+
+        assert_eq!(
+            output.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(0xff),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R1),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(0x1f),
+                TokenType::Opcode(0x45),
+            ]
+        );
+
+        assert_eq!(output[3].lexeme().string(), "ff");
+
+        // Words with non-hex characters are still identifiers.
+        let f = f_man.new_file(String::new(), "fg".to_string());
+
+        assert_eq!(
+            Lexer::tokenize_with_default(f, NumericType::Hex).unwrap()[0].token_type(),
+            TokenType::Identifier
+        );
+    }
+
+    #[test]
+    fn test_bin_default() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "101".to_string());
+
+        assert_eq!(
+            Lexer::tokenize_with_default(f, NumericType::Bin).unwrap()[0].token_type(),
+            TokenType::UnsignedIntegerLiteral(0b101)
+        );
+
+        let f = f_man.new_file(String::new(), "2".to_string());
+
+        assert_eq!(
+            Lexer::tokenize_with_default(f.clone(), NumericType::Bin).unwrap_err(),
+            LexerError::UnexpectedCharacter('2', Position::new(0, 0, 0), Either::Left(f))
+        );
+    }
+
+    #[test]
+    fn test_lexer_builder() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "\t5".to_string());
+
+        let mut lexer = LexerBuilder::new()
+            .default_numeric(NumericType::Float)
+            .tab_width(4)
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
+
+        assert_eq!(
+            lexer.into_tokens(),
+            vec![new_token!(
+                TokenType::FloatLiteral(5.0),
+                1,
+                0,
+                4,
+                1,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "ldi 52, $r0 # note\n/* block */".to_string());
+
+        let mut lexer = LexerBuilder::new()
+            .keep_comments(true)
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
+
+        let tokens = lexer.into_tokens();
+
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(
+            tokens[4],
+            new_token!(TokenType::Comment, 13, 5, Either::Left(f.clone()))
+        );
+        assert_eq!(tokens[4].lexeme().string(), " note");
+        assert_eq!(
+            tokens[5],
+            new_token!(TokenType::Comment, 21, 1, 2, 7, Either::Left(f.clone()))
+        );
+
+        assert_eq!(Lexer::tokenize(f.clone()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_emit_newlines() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "\nhalt # stop\n\r\n\nhalt\n".to_string());
+
+        let mut lexer = LexerBuilder::new()
+            .emit_newlines(true)
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
+
+        let tokens = lexer.into_tokens();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            vec![
+                TokenType::Opcode(0x45),
+                TokenType::Newline,
+                TokenType::Opcode(0x45),
+                TokenType::Newline,
+            ]
+        );
+        assert_eq!(tokens[1].lexeme().string(), "\n");
+
+        assert_eq!(Lexer::tokenize(f.clone()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(
+            String::new(),
+            "\thalt\n  halt\r\n\t halt\nhalt \t".to_string(),
+        );
+
+        let lexer = LexerBuilder::new()
+            .lint_indentation(true)
+            .build(Either::Left(f.clone()));
+
+        assert_eq!(
+            lexer.warnings(),
+            &[LexerWarning::MixedIndentation(
+                Position::new(14, 2, 0),
+                Either::Left(f.clone())
+            )]
+        );
+
+        assert!(
+            Lexer::from_source(Either::Left(f.clone()), NumericType::Unsigned)
+                .warnings()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_section_directive() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "%section .data".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![
+                new_token!(TokenType::Section, 1, 7, Either::Left(f.clone())),
+                new_token!(TokenType::LocalLabel, 9, 5, Either::Left(f.clone())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment_char() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "halt ; stop # here\nhalt".to_string());
+
+        let mut lexer = LexerBuilder::new()
+            .comment_char(';')
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
+
+        assert_eq!(
+            lexer
+                .into_tokens()
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>(),
+            vec![TokenType::Opcode(0x45), TokenType::Opcode(0x45)]
+        );
+
+        assert!(matches!(
+            Lexer::tokenize(f.clone()),
+            Err(LexerError::UnexpectedCharacter(';', _, _))
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_reserved_comment_char() {
+        LexerBuilder::new().comment_char('$');
+    }
+
+    #[test]
+    fn test_comment_styles() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            String::new(),
+            "# one\nhalt ; two\n// three\n4 / 2 {- four {- five -} -}\nhalt // six".to_string(),
+        );
+
+        let mut lexer = LexerBuilder::new()
+            .keep_comments(true)
+            .comment_styles(vec![
+                CommentStyle::Line('#'),
+                CommentStyle::Line(';'),
+                CommentStyle::LineStr("//"),
+                CommentStyle::Block {
+                    open: "{-",
+                    close: "-}",
+                },
+            ])
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
+
+        let tokens = lexer.into_tokens();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            vec![
+                TokenType::Comment,
+                TokenType::Opcode(0x45),
+                TokenType::Comment,
+                TokenType::Comment,
+                TokenType::UnsignedIntegerLiteral(4),
+                TokenType::Operator(Operator::Divide),
+                TokenType::UnsignedIntegerLiteral(2),
+                TokenType::Comment,
+                TokenType::Opcode(0x45),
+                TokenType::Comment,
+            ]
+        );
+
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|t| t.token_type() == TokenType::Comment)
+                .map(|t| t.lexeme().string())
+                .collect::<Vec<_>>(),
+            vec![" one", " two", " three", " four {- five -} ", " six"]
+        );
+
+        assert!(matches!(
+            Lexer::tokenize(f.clone()),
+            Err(LexerError::UnexpectedCharacter(';', _, _))
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_comment_marker() {
+        LexerBuilder::new().comment_styles(vec![CommentStyle::LineStr("")]);
+    }
+
+    #[test]
+    fn test_integer_width_annotation() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0u8:255, 0i8:-128".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![
+                new_token!(
+                    TokenType::UnsignedIntegerLiteral(255),
+                    4,
+                    3,
+                    Either::Left(f.clone())
+                )
+                .with_width(8),
+                new_token!(TokenType::Comma, 7, 1, Either::Left(f.clone())),
+                new_token!(
+                    TokenType::SignedIntegerLiteral(-128),
+                    13,
+                    4,
+                    Either::Left(f.clone())
+                )
+                .with_width(8),
+            ]
+        );
+
+        let f = f_man.new_file(String::new(), "0u16:5 0i64:-1 7".to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output.iter().map(|t| t.width()).collect::<Vec<_>>(),
+            vec![Some(16), Some(64), None]
+        );
+
+        let f = f_man.new_file(String::new(), "0u8:256".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidUnsignedIntegerLiteral(TextRange::new(
+                Position::new(4, 0, 4),
+                Position::new(7, 0, 7),
+                Either::Left(f.clone())
+            ))
+        );
+
+        // Without a colon the digits remain the value.
+        let f = f_man.new_file(String::new(), "0u8".to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(output[0].token_type(), TokenType::UnsignedIntegerLiteral(8));
+        assert_eq!(output[0].width(), None);
+    }
+
+    #[test]
+    fn test_register_range() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "$r0-$r3".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::RegisterRange(Register::R0, Register::R3),
+                1,
+                6,
+                Either::Left(f.clone())
+            )]
+        );
+
+        for input in ["$r3-$r0", "$rsp-$r3"] {
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            assert_eq!(
+                Lexer::tokenize(f.clone()).unwrap_err(),
+                LexerError::InvalidRegisterRange(TextRange::new(
+                    Position::new(1, 0, 1),
+                    Position::new(input.len(), 0, input.len()),
+                    Either::Left(f.clone())
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_register_r15_invalid() {
+        let input = "$r15, $r0";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidRegister(TextRange::new(
+                Position::new(1, 0, 1),
+                Position::new(4, 0, 4),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    mod directives {
+        use super::*;
+
+        macro_rules! test_directive {
+            ($name:ident, $input:expr, $tp:expr) => {
+                #[test]
+                fn $name() {
+                    let input: &str = $input;
+
+                    let mut f_man = FileInfoManager::new();
+
+                    let f = f_man.new_file(String::new(), input.to_string());
+
+                    let output = Lexer::tokenize(f.clone()).unwrap();
+
+                    assert_eq!(
+                        output,
+                        vec![new_token!($tp, 1, input.len() - 1, Either::Left(f.clone()))]
+                    );
+                }
+            };
+        }
+
+        #[test]
+        fn test_unknown_directive_suggestion() {
+            let input = "%repaet 3";
+
+            let mut f_man = FileInfoManager::new();
+
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+            assert_eq!(
+                output,
+                LexerError::UnknownDirectiveWithSuggestion(
+                    TextRange::new(
+                        Position::new(1, 0, 1),
+                        Position::new(7, 0, 7),
+                        Either::Left(f.clone())
+                    ),
+                    "repeat"
+                )
+            );
+            assert!(output.to_string().contains("did you mean %repeat?"));
+        }
+
+        test_directive!(test_repeat, "%repeat", TokenType::Repeat);
+        test_directive!(test_end_repeat, "%end_repeat", TokenType::EndRepeat);
+        test_directive!(test_if, "%if", TokenType::If);
+        test_directive!(test_ifdef, "%ifdef", TokenType::Ifdef);
+        test_directive!(test_ifndef, "%ifndef", TokenType::Ifndef);
+        test_directive!(test_elif, "%elif", TokenType::Elif);
+        test_directive!(test_else, "%else", TokenType::Else);
+        test_directive!(test_end_if, "%end_if", TokenType::Endif);
+        test_directive!(test_import, "%import", TokenType::Import);
+        test_directive!(test_const, "%const", TokenType::Constant);
+        test_directive!(test_macro, "%macro", TokenType::Macro);
+        test_directive!(test_end_macro, "%end_macro", TokenType::EndMacro);
+        test_directive!(test_align, "%align", TokenType::Align);
+        test_directive!(test_define_byte, "%db", TokenType::DefineByte);
+        test_directive!(test_define_word, "%dw", TokenType::DefineWord);
+        test_directive!(test_define_dword, "%dd", TokenType::DefineDword);
+        test_directive!(test_define_qword, "%dq", TokenType::DefineQword);
+        test_directive!(test_ascii, "%ascii", TokenType::Ascii);
+        test_directive!(test_asciiz, "%asciiz", TokenType::Asciiz);
+        test_directive!(test_fill, "%fill", TokenType::Fill);
+        test_directive!(test_org, "%org", TokenType::Org);
+        test_directive!(test_section, "%section", TokenType::Section);
+        test_directive!(test_error, "%error", TokenType::ErrorDirective);
+        test_directive!(test_global, "%global", TokenType::Global);
+        test_directive!(test_extern, "%extern", TokenType::Extern);
+
+        #[test]
+        fn test_entry() {
+            let input = "%entry MAIN";
+
+            let mut f_man = FileInfoManager::new();
+
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            let output = Lexer::tokenize(f.clone()).unwrap();
+
+            assert_eq!(
+                output,
+                vec![
+                    new_token!(TokenType::Entry, 1, 5, Either::Left(f.clone())),
+                    new_token!(TokenType::Identifier, 7, 4, Either::Left(f.clone())),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_asciiz_operand() {
+            let input = "%asciiz \"hi\\n\"";
+
+            let mut f_man = FileInfoManager::new();
+
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            let output = Lexer::tokenize(f.clone()).unwrap();
+
+            assert_eq!(
+                output,
+                vec![
+                    new_token!(TokenType::Asciiz, 1, 6, Either::Left(f.clone())),
+                    new_token!(
+                        TokenType::String("hi\n".into()),
+                        9,
+                        4,
+                        Either::Left(f.clone())
+                    ),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_align_operand() {
+            let input = "%align 16";
+
+            let mut f_man = FileInfoManager::new();
+
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            let output = Lexer::tokenize(f.clone()).unwrap();
+
+            assert_eq!(
+                output,
+                vec![
+                    new_token!(TokenType::Align, 1, 5, Either::Left(f.clone())),
+                    new_token!(
+                        TokenType::UnsignedIntegerLiteral(16),
+                        7,
+                        2,
+                        Either::Left(f.clone())
+                    ),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_identifier() {
+        let input: &str = "MAIN";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::Identifier,
+                0,
+                input.len(),
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_identifier_with_digits_label() {
+        let input: &str = "loop1:";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Identifier, 0, 5, Either::Left(f.clone())),
+                new_token!(TokenType::Colon, 5, 1, Either::Left(f.clone()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifier_with_digits_reference() {
+        let input: &str = "jmp loop1";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Opcode(0x37), 0, 3, Either::Left(f.clone())),
+                new_token!(TokenType::Identifier, 4, 5, Either::Left(f.clone()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        let input: &str = "\"MAIN\"";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::String(Rc::from("MAIN")),
+                1,
+                4,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let input: &str = "\"hello\\n\\t\\\\\\\"\\0\\x41\"";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::String(Rc::from("hello\n\t\\\"\0A")),
+                1,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_string_unterminated() {
+        let input: &str = "\"hello";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::UnterminatedString(TextRange::new(
+                Position::new(1, 0, 1),
+                Position::new(6, 0, 6),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_string_invalid_escape() {
+        let input: &str = "\"a\\qb\"";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidEscape(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(4, 0, 4),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_string_non_ascii_hex_escape() {
+        let input: &str = "\"a\\x80\"";
+
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidEscape(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(6, 0, 6),
+                Either::Left(f.clone())
+            ))
+        );
+
+        let f = f_man.new_file(String::new(), "\"\\x7f\"".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap()[0].token_type(),
+            TokenType::String(Rc::from("\x7f"))
+        );
+    }
+
+    #[test]
+    fn test_char() {
+        let input: &str = "'A'";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(65),
+                1,
+                1,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_char_escape() {
+        let input: &str = "'\\n'";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(10),
+                1,
+                2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_char_empty() {
+        let input: &str = "''";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidCharLiteral(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(2, 0, 2),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_char_multiple() {
+        let input: &str = "'ab'";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidCharLiteral(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(4, 0, 4),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_opcode_and_identifier() {
+        let input: &str = "call MAIN";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Opcode(0x43), 0, 4, Either::Left(f.clone())),
+                new_token!(TokenType::Identifier, 5, 4, Either::Left(f.clone()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_instruction_example_ldi() {
+        let input = "ldi 52, $r0";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Opcode(3), 0, 3, Either::Left(f.clone())),
+                new_token!(
+                    TokenType::UnsignedIntegerLiteral(52),
+                    4,
+                    2,
+                    Either::Left(f.clone())
+                ),
+                new_token!(TokenType::Comma, 6, 1, Either::Left(f.clone())),
+                new_token!(
+                    TokenType::Register(Register::R0),
+                    9,
+                    2,
+                    Either::Left(f.clone())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instruction_examples() {
+        let input = "ldi 0u52, $r1\nmalloc $r0, $r1";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+        let expected = vec![
+            new_token!(TokenType::Opcode(0x3), 0, 3, Either::Left(f.clone())),
+            new_token!(
+                TokenType::UnsignedIntegerLiteral(52),
+                6,
+                2,
+                Either::Left(f.clone())
+            ),
+            new_token!(TokenType::Comma, 8, 1, Either::Left(f.clone())),
+            new_token!(
+                TokenType::Register(Register::R1),
+                11,
+                2,
+                Either::Left(f.clone())
+            ),
+            new_token!(TokenType::Opcode(0x9), 14, 1, 0, 6, Either::Left(f.clone())),
+            new_token!(
+                TokenType::Register(Register::R0),
+                22,
+                1,
+                8,
+                2,
+                Either::Left(f.clone())
+            ),
+            new_token!(TokenType::Comma, 24, 1, 10, 1, Either::Left(f.clone())),
+            new_token!(
+                TokenType::Register(Register::R1),
+                27,
+                1,
+                13,
+                2,
+                Either::Left(f.clone())
+            ),
+        ];
+
+        assert_eq!(output.len(), expected.len());
+
+        for i in 0..output.len() {
+            assert_eq!(output[i], expected[i]);
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        let input = "0x2abcdef";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(0x2abcdef),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        )
+    }
+
+    #[test]
+    fn test_hex_max() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0xFFFFFFFFFFFFFFFF".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(u64::MAX),
+                2,
+                16,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let f = f_man.new_file(String::new(), "0x10000000000000000".to_string());
+        let err = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            err,
+            LexerError::InvalidHexLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(19, 0, 19),
+                Either::Left(f.clone())
+            ))
+        );
+        assert!(err.to_string().contains("exceeds 64 bits"));
+    }
+
+    #[test]
+    fn test_empty_radix_literals() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0x".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidHexLiteral(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(2, 0, 2),
+                Either::Left(f.clone())
+            ))
+        );
+
+        let f = f_man.new_file(String::new(), "0b, $r0".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidBinaryLiteral(TextRange::new(
+                Position::new(0, 0, 0),
+                Position::new(2, 0, 2),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_hex_uppercase_prefix() {
+        let mut f_man = FileInfoManager::new();
+        let upper = f_man.new_file(String::new(), "0XFF".to_string());
+        let lower = f_man.new_file(String::new(), "0xff".to_string());
+
+        let upper: Vec<TokenType> = Lexer::tokenize(upper)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type())
+            .collect();
+        let lower: Vec<TokenType> = Lexer::tokenize(lower)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type())
+            .collect();
+
+        assert_eq!(upper, vec![TokenType::UnsignedIntegerLiteral(0xff)]);
+        assert_eq!(upper, lower);
+    }
+
+    #[test]
+    fn test_bin_uppercase_prefix() {
+        let mut f_man = FileInfoManager::new();
+        let upper = f_man.new_file(String::new(), "0B1010".to_string());
+        let lower = f_man.new_file(String::new(), "0b1010".to_string());
+
+        let upper: Vec<TokenType> = Lexer::tokenize(upper)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type())
+            .collect();
+        let lower: Vec<TokenType> = Lexer::tokenize(lower)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token_type())
+            .collect();
+
+        assert_eq!(upper, vec![TokenType::UnsignedIntegerLiteral(0b1010)]);
+        assert_eq!(upper, lower);
+    }
+
+    #[test]
+    fn test_bin() {
+        let input = "0b01100110";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(0b01100110),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        )
+    }
+
+    #[test]
+    fn test_bin_leading_zeros() {
+        let mut f_man = FileInfoManager::new();
+
+        let inputs = [
+            ("0b0010", 0b0010),
+            ("0b0", 0),
+            (
+                "0b0000000000000000000000000000000010000000000000000000000000000001",
+                0x80000001,
+            ),
+        ];
+
+        for (input, value) in inputs {
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            assert_eq!(
+                Lexer::tokenize(f.clone()).unwrap(),
+                vec![new_token!(
+                    TokenType::UnsignedIntegerLiteral(value),
+                    2,
+                    input.len() - 2,
+                    Either::Left(f.clone())
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_bin_64bits() {
+        let input = "0b1110011001100110011001100110011001100110011001100110011001100110";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(
+                    0b1110011001100110011001100110011001100110011001100110011001100110
+                ),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        )
+    }
+
+    #[test]
+    fn test_bin_65bits() {
+        let input = "0b11100110011001100110011001100110011001100110011001100110011001101";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidBinaryLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            ))
+        )
+    }
+
+    #[test]
+    fn test_negative_hex() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0x-1".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::SignedIntegerLiteral(-1),
+                2,
+                2,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let f = f_man.new_file(String::new(), "0x-8000000000000000".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap()[0].token_type(),
+            TokenType::SignedIntegerLiteral(i64::MIN)
+        );
+
+        let f = f_man.new_file(String::new(), "0x-8000000000000001".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidHexLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(19, 0, 19),
+                Either::Left(f.clone())
+            ))
+        );
+
+        let f = f_man.new_file(String::new(), "0x-".to_string());
+        assert!(matches!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidHexLiteral(_)
+        ));
+    }
+
+    #[test]
+    fn test_negative_bin() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0b-1".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::SignedIntegerLiteral(-1),
+                2,
+                2,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let f = f_man.new_file(String::new(), "0b-1010".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap()[0].token_type(),
+            TokenType::SignedIntegerLiteral(-10)
+        );
+
+        let input = "0b-1000000000000000000000000000000000000000000000000000000000000001";
+        let f = f_man.new_file(String::new(), input.to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidBinaryLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_float_bits() {
+        let mut f_man = FileInfoManager::new();
+
+        let f = f_man.new_file(String::new(), "0f0x400921FB54442D18".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap(),
+            vec![new_token!(
+                TokenType::FloatLiteral(core::f64::consts::PI),
+                2,
+                18,
+                Either::Left(f.clone())
+            )]
+        );
+
+        let f = f_man.new_file(String::new(), "0f0x400921FB".to_string());
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidFloatLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(12, 0, 12),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_signed_int() {
+        let input = "0i-123";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::SignedIntegerLiteral(-123),
+                2,
+                4,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_signed_int_min() {
+        let input = "0i-9223372036854775808";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::SignedIntegerLiteral(i64::MIN),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_signed_int_overflow() {
+        let input = "0i-9223372036854775809";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidSignedIntegerLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_unsigned_int_overflow() {
+        let input = "0u9999999999999999999999999";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::InvalidUnsignedIntegerLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_int_without_digits() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "0i-".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidSignedIntegerLiteral(TextRange::new(
+                Position::new(3, 0, 3),
+                Position::new(3, 0, 3),
+                Either::Left(f.clone())
+            ))
+        );
+
+        let f = f_man.new_file(String::new(), "0u".to_string());
+
+        assert_eq!(
+            Lexer::tokenize(f.clone()).unwrap_err(),
+            LexerError::InvalidUnsignedIntegerLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(2, 0, 2),
+                Either::Left(f.clone())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        let input = "0f-123.333333";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::FloatLiteral(-123.333333),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_float_zero() {
+        let input = "0f0.0";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::FloatLiteral(0.0),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_float_exponent() {
+        let input = "0f1.5e10";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::FloatLiteral(1.5e10),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_float_negative_exponent() {
+        let input = "0f2e-3";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::FloatLiteral(2e-3),
+                2,
+                input.len() - 2,
+                Either::Left(f.clone())
+            )]
+        );
+    }
 
-    use super::*;
-    use alloc::string::{String, ToString};
-    use alloc::vec;
+    #[test]
+    fn test_float_truncated_exponent() {
+        let input = "0f1.0e+";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
 
-    macro_rules! new_token {
-        ($tp:expr, $col:expr, $len:expr, $file:expr) => {
-            Token::new(
-                $tp,
-                TextRange::new(
-                    Position::new($col, 0, $col),
-                    Position::new($col + $len, 0, $col + $len),
-                    $file,
-                ),
-            )
-        };
+        assert_eq!(
+            output,
+            LexerError::InvalidFloatLiteral(TextRange::new(
+                Position::new(2, 0, 2),
+                Position::new(input.len(), 0, input.len()),
+                Either::Left(f.clone())
+            ))
+        );
+    }
 
-        ($tp:expr, $index:expr, $row:expr, $col:expr, $len:expr, $file:expr) => {
-            Token::new(
-                $tp,
-                TextRange::new(
-                    Position::new($index, $row, $col),
-                    Position::new($index + $len, $row, $col + $len),
-                    $file,
-                ),
+    #[test]
+    fn test_float_second_decimal_point() {
+        let input = "0f1.2.3";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::UnexpectedSecondDecimalPoint(
+                Position::new(5, 0, 5),
+                Either::Left(f.clone())
             )
-        };
+        );
     }
 
     #[test]
-    fn test_registers() {
-        let input = "$rsp $rfp $rou $rfl $rra $rrb $r0 $r1 $r2 $r3 $r4 $r5 $r6 $r7 $r8 $r9";
+    fn test_crlf_line_break() {
+        let input = "a\r\nb";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Identifier, 0, 1, Either::Left(f.clone())),
+                new_token!(TokenType::Identifier, 3, 1, 0, 1, Either::Left(f.clone()))
+            ]
+        );
+    }
 
+    #[test]
+    fn test_cr_line_break() {
+        let input = "a\rb";
         let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Identifier, 0, 1, Either::Left(f.clone())),
+                new_token!(TokenType::Identifier, 2, 1, 0, 1, Either::Left(f.clone()))
+            ]
+        );
+    }
 
+    #[test]
+    fn test_tab_width() {
+        let input = "\tldi";
+        let mut f_man = FileInfoManager::new();
         let f = f_man.new_file(String::new(), input.to_string());
+        let mut lexer =
+            Lexer::from_source(Either::Left(f.clone()), NumericType::Unsigned).with_tab_width(4);
+
+        lexer.process().unwrap();
+
+        assert_eq!(
+            lexer.into_tokens(),
+            vec![new_token!(
+                TokenType::Opcode(3),
+                1,
+                0,
+                4,
+                3,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_tab_width_in_strings_and_comments() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "\"a\tb\" ?".to_string());
+
+        let mut lexer =
+            Lexer::from_source(Either::Left(f.clone()), NumericType::Unsigned).with_tab_width(4);
+
+        assert_eq!(
+            lexer.process(),
+            Err(LexerError::UnexpectedCharacter(
+                '?',
+                Position::new(6, 0, 9),
+                Either::Left(f.clone())
+            ))
+        );
+
+        let f = f_man.new_file(String::new(), "/*\t*/ halt".to_string());
+
+        let mut lexer =
+            Lexer::from_source(Either::Left(f.clone()), NumericType::Unsigned).with_tab_width(4);
+        lexer.process().unwrap();
+
+        assert_eq!(
+            lexer.into_tokens()[0].lexeme().start(),
+            Position::new(6, 0, 9)
+        );
+    }
 
+    #[test]
+    fn test_comment_eol() {
+        let input = "ldi 52, $r0 #452";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
         let output = Lexer::tokenize(f.clone()).unwrap();
 
-        for i in 0..16 {
-            assert_eq!(
-                output[i],
+        assert_eq!(
+            output,
+            vec![
+                new_token!(TokenType::Opcode(3), 0, 3, Either::Left(f.clone())),
                 new_token!(
-                    TokenType::Register(Register::from(i as u8)),
-                    1 + if i >= 6 { 5 * 6 + 4 * (i - 6) } else { 5 * i },
-                    if i >= 6 { 2 } else { 3 },
+                    TokenType::UnsignedIntegerLiteral(52),
+                    4,
+                    2,
                     Either::Left(f.clone())
-                )
-            );
-        }
+                ),
+                new_token!(TokenType::Comma, 6, 1, Either::Left(f.clone())),
+                new_token!(
+                    TokenType::Register(Register::R0),
+                    9,
+                    2,
+                    Either::Left(f.clone())
+                ),
+            ]
+        );
     }
 
-    mod directives {
-        use super::*;
+    #[test]
+    fn test_comment_eol_followed_by_line() {
+        let input = "ldi 52, $r0 # comment\nhalt";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
 
-        macro_rules! test_directive {
-            ($name:ident, $input:expr, $tp:expr) => {
-                #[test]
-                fn $name() {
-                    let input: &str = $input;
+        assert_eq!(output.len(), 5);
+        assert_eq!(
+            output[4],
+            new_token!(TokenType::Opcode(69), 22, 1, 0, 4, Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_block_comment() {
+        let input = "ldi 52, $r0\n/* first\nsecond */\nhalt";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(output.len(), 5);
+        assert_eq!(
+            output[4],
+            new_token!(TokenType::Opcode(69), 31, 3, 0, 4, Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_block_comment_unterminated() {
+        let input = "ldi 52, $r0 /* comment";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::UnterminatedBlockComment(Position::new(12, 0, 12), Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_block_comment_nested() {
+        let input = "/* a /* b /* c */\n */ d */halt";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output,
+            vec![new_token!(
+                TokenType::Opcode(69),
+                26,
+                1,
+                8,
+                4,
+                Either::Left(f.clone())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_block_comment_nested_unterminated() {
+        let input = "halt /* a /* b */";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::UnterminatedBlockComment(Position::new(5, 0, 5), Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = "4 * (A + 0x2) / 2 % 3 << 1 >> 1 & 7 | 8 ^ B - 1";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            output.iter().map(|t| t.token_type()).collect::<Vec<_>>(),
+            vec![
+                TokenType::UnsignedIntegerLiteral(4),
+                TokenType::Operator(Operator::Multiply),
+                TokenType::LParen,
+                TokenType::Identifier,
+                TokenType::Operator(Operator::Add),
+                TokenType::UnsignedIntegerLiteral(2),
+                TokenType::RParen,
+                TokenType::Operator(Operator::Divide),
+                TokenType::UnsignedIntegerLiteral(2),
+                TokenType::Operator(Operator::Remainder),
+                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::Operator(Operator::ShiftLeft),
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Operator(Operator::ShiftRight),
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Operator(Operator::And),
+                TokenType::UnsignedIntegerLiteral(7),
+                TokenType::Operator(Operator::Or),
+                TokenType::UnsignedIntegerLiteral(8),
+                TokenType::Operator(Operator::Xor),
+                TokenType::Identifier,
+                TokenType::Operator(Operator::Subtract),
+                TokenType::UnsignedIntegerLiteral(1),
+            ]
+        );
+
+        assert_eq!(output[11].lexeme().string(), "<<");
+        assert_eq!(output[9].lexeme().string(), "%");
+    }
+
+    #[test]
+    fn test_binary_operator_after_operand() {
+        let mut f_man = FileInfoManager::new();
+        let mut types = |input: &str| {
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            return Lexer::tokenize(f)
+                .unwrap()
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>();
+        };
+
+        assert_eq!(
+            types("5-3 A-1 (2)-1 A %B"),
+            vec![
+                TokenType::UnsignedIntegerLiteral(5),
+                TokenType::Operator(Operator::Subtract),
+                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::Identifier,
+                TokenType::Operator(Operator::Subtract),
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::LParen,
+                TokenType::UnsignedIntegerLiteral(2),
+                TokenType::RParen,
+                TokenType::Operator(Operator::Subtract),
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Identifier,
+                TokenType::Operator(Operator::Remainder),
+                TokenType::Identifier,
+            ]
+        );
+
+        assert_eq!(
+            types("%repeat 3\nldi $r0, 5 %end_repeat"),
+            vec![
+                TokenType::Repeat,
+                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(5),
+                TokenType::EndRepeat,
+            ]
+        );
+
+        assert_eq!(
+            types("%repeat i 3 ldi $r0, i %end_repeat"),
+            vec![
+                TokenType::Repeat,
+                TokenType::Identifier,
+                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::Identifier,
+                TokenType::EndRepeat,
+            ]
+        );
+
+        assert_eq!(
+            types("%if X %error \"m\""),
+            vec![
+                TokenType::If,
+                TokenType::Identifier,
+                TokenType::ErrorDirective,
+                TokenType::String("m".into()),
+            ]
+        );
+
+        assert_eq!(
+            types("X 5\n%const"),
+            vec![
+                TokenType::Identifier,
+                TokenType::UnsignedIntegerLiteral(5),
+                TokenType::Constant,
+            ]
+        );
+
+        let f = f_man.new_file(String::new(), "B -1".to_string());
+
+        assert_eq!(
+            Lexer::tokenize_with_default(f, NumericType::Signed)
+                .unwrap()
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>(),
+            vec![TokenType::Identifier, TokenType::SignedIntegerLiteral(-1)]
+        );
+    }
+
+    #[test]
+    fn test_lone_angle_bracket() {
+        let input = "ldi 52 < $r0";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let output = Lexer::tokenize(f.clone()).unwrap_err();
+
+        assert_eq!(
+            output,
+            LexerError::UnexpectedCharacter('<', Position::new(7, 0, 7), Either::Left(f.clone()))
+        );
+    }
+
+    #[test]
+    fn test_file_and_string_sources_match() {
+        let mut input = String::new();
 
-                    let mut f_man = FileInfoManager::new();
+        while input.len() < 1024 {
+            input.push_str("loop:\n\tldi $r0, 0u52 # load\n\tmalloc $r1, $r0\n\tjmp loop\n");
+        }
 
-                    let f = f_man.new_file(String::new(), input.to_string());
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.clone());
 
-                    let output = Lexer::tokenize(f.clone()).unwrap();
+        let from_file = Lexer::tokenize(f.clone()).unwrap();
+        let from_string = Lexer::tokenize_string(input).unwrap();
 
-                    assert_eq!(
-                        output,
-                        vec![new_token!($tp, 1, input.len() - 1, Either::Left(f.clone()))]
-                    );
-                }
-            };
+        assert_eq!(from_file.len(), from_string.len());
+
+        for (a, b) in from_file.iter().zip(from_string.iter()) {
+            assert_eq!(a.token_type(), b.token_type());
+            assert_eq!(a.lexeme().start(), b.lexeme().start());
+            assert_eq!(a.lexeme().end(), b.lexeme().end());
+            assert_eq!(a.lexeme().string(), b.lexeme().string());
         }
+    }
 
-        test_directive!(test_repeat, "%repeat", TokenType::Repeat);
-        test_directive!(test_end_repeat, "%end_repeat", TokenType::EndRepeat);
-        test_directive!(test_if, "%if", TokenType::If);
-        test_directive!(test_else, "%else", TokenType::Else);
-        test_directive!(test_end_if, "%end_if", TokenType::Endif);
-        test_directive!(test_import, "%import", TokenType::Import);
-        test_directive!(test_const, "%const", TokenType::Constant);
+    #[test]
+    fn test_stream_matches_tokenize() {
+        let input = "start:\n\tldi $r0, 0u10 # load\n\tmalloc $r0, $r0\n\tjmp start";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+
+        let streamed: Result<Vec<Token>, LexerError> = Lexer::stream(f.clone()).collect();
+
+        assert_eq!(streamed.unwrap(), Lexer::tokenize(f.clone()).unwrap());
     }
 
     #[test]
-    fn test_identifier() {
-        let input: &str = "MAIN";
+    #[allow(deprecated)]
+    fn test_tokenize_str_matches_chars() {
+        let mut input = String::new();
+        let mut i = 0;
+
+        while input.len() < 1024 {
+            input.push_str(&format!(
+                "L{}:\tldi $r{}, 0x{:X} # step\n\tmalloc $r0, $sp\n\tjmp L{}\n%ascii \"a\\tb\"\n",
+                i,
+                i % 10,
+                i,
+                i
+            ));
+            i += 1;
+        }
 
         let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.clone());
 
-        let f = f_man.new_file(String::new(), input.to_string());
+        let mut lexer = Lexer::new_file(input.chars().collect(), f.clone(), NumericType::Unsigned);
+        lexer.process().unwrap();
 
-        let output = Lexer::tokenize(f.clone()).unwrap();
+        let tokens = lexer.into_tokens();
 
+        assert_eq!(Lexer::tokenize_str(&input, f.clone()).unwrap(), tokens);
         assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::Identifier,
-                0,
-                input.len(),
-                Either::Left(f.clone())
-            )]
+            Lexer::tokenize_str(f.contents(), f.clone()).unwrap(),
+            tokens
+        );
+
+        let mut lexer = Lexer::new_string(
+            input.chars().collect(),
+            Rc::new(input.clone().into()),
+            NumericType::Unsigned,
         );
+        lexer.process().unwrap();
+
+        assert_eq!(lexer.into_tokens(), Lexer::tokenize_string(input).unwrap());
     }
 
     #[test]
-    fn test_string() {
-        let input: &str = "\"MAIN\"";
-
+    fn test_checkpoint_restore() {
         let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            String::new(),
+            "ldi $r0, 5\nmalloc $r1, $r0\nhalt".to_string(),
+        );
 
-        let f = f_man.new_file(String::new(), input.to_string());
+        let mut lexer = LexerBuilder::new()
+            .emit_eof(true)
+            .build(Either::Left(f.clone()));
 
-        let output = Lexer::tokenize(f.clone()).unwrap();
+        for _ in 0..4 {
+            let token = lexer.next_token().unwrap().unwrap();
+            lexer.tokens.push(token);
+        }
 
-        assert_eq!(
-            output,
-            vec![new_token!(TokenType::String, 1, 4, Either::Left(f.clone()))]
-        );
+        let checkpoint = lexer.checkpoint();
+        let saved = lexer.clone();
+
+        lexer.process().unwrap();
+        let first = lexer.tokens.clone();
+
+        lexer.restore(checkpoint);
+
+        assert_eq!(lexer, saved);
+        assert_eq!(lexer.checkpoint(), checkpoint);
+
+        lexer.process().unwrap();
+
+        assert_eq!(lexer.into_tokens(), first);
     }
 
     #[test]
-    fn test_opcode_and_identifier() {
-        let input: &str = "call MAIN";
+    fn test_emit_eof() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
+        let f = f_man.new_file(String::new(), "halt\nhalt ".to_string());
 
+        let tokens: Vec<Token> = LexerBuilder::new()
+            .emit_eof(true)
+            .build(Either::Left(f.clone()))
+            .into_stream()
+            .map(|t| t.unwrap())
+            .collect();
+
+        assert_eq!(tokens.len(), 3);
         assert_eq!(
-            output,
-            vec![
-                new_token!(TokenType::Opcode(0x43), 0, 4, Either::Left(f.clone())),
-                new_token!(TokenType::Identifier, 5, 4, Either::Left(f.clone()))
-            ]
+            tokens[2],
+            new_token!(TokenType::Eof, 10, 1, 5, 0, Either::Left(f.clone()))
         );
+
+        assert_eq!(Lexer::tokenize(f.clone()).unwrap().len(), 2);
     }
 
     #[test]
-    fn test_single_instruction_example_ldi() {
-        let input = "ldi 52, $r0";
+    fn test_unknown_directive_policy() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
+        let f = f_man.new_file(String::new(), "%mytool_hint\nhalt".to_string());
+
+        assert!(matches!(
+            Lexer::tokenize(f.clone()),
+            Err(LexerError::UnknownDirective(_))
+        ));
+
+        let mut lexer = LexerBuilder::new()
+            .unknown_directive_policy(UnknownDirectivePolicy::Ignore)
+            .build(Either::Left(f.clone()));
+
+        lexer.process().unwrap();
 
         assert_eq!(
-            output,
+            lexer.into_tokens(),
             vec![
-                new_token!(TokenType::Opcode(3), 0, 3, Either::Left(f.clone())),
                 new_token!(
-                    TokenType::UnsignedIntegerLiteral(52),
-                    4,
-                    2,
+                    TokenType::RawDirective,
+                    1,
+                    0,
+                    1,
+                    11,
                     Either::Left(f.clone())
                 ),
-                new_token!(TokenType::Comma, 6, 1, Either::Left(f.clone())),
                 new_token!(
-                    TokenType::Register(Register::R0),
-                    9,
-                    2,
+                    TokenType::Opcode(0x45),
+                    13,
+                    1,
+                    0,
+                    4,
                     Either::Left(f.clone())
                 ),
             ]
@@ -832,188 +4240,157 @@ mod tests {
     }
 
     #[test]
-    fn test_instruction_examples() {
-        let input = "ldi 0u52, $r1\nmalloc $r0, $r1";
+    fn test_radix_prefixes() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
-        let expected = vec![
-            new_token!(TokenType::Opcode(0x3), 0, 3, Either::Left(f.clone())),
-            new_token!(
-                TokenType::UnsignedIntegerLiteral(52),
-                6,
-                2,
-                Either::Left(f.clone())
-            ),
-            new_token!(TokenType::Comma, 8, 1, Either::Left(f.clone())),
-            new_token!(
-                TokenType::Register(Register::R1),
-                11,
-                2,
-                Either::Left(f.clone())
-            ),
-            new_token!(TokenType::Opcode(0x9), 14, 1, 0, 6, Either::Left(f.clone())),
-            new_token!(
-                TokenType::Register(Register::R0),
-                22,
-                1,
-                8,
-                2,
-                Either::Left(f.clone())
-            ),
-            new_token!(TokenType::Comma, 24, 1, 10, 1, Either::Left(f.clone())),
-            new_token!(
-                TokenType::Register(Register::R1),
-                27,
-                1,
-                13,
-                2,
-                Either::Left(f.clone())
-            ),
-        ];
+        let f = f_man.new_file(String::new(), "0x1F, 0f1.5".to_string());
 
-        assert_eq!(output.len(), expected.len());
+        assert_eq!(
+            Lexer::tokenize(f.clone())
+                .unwrap()
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>(),
+            vec![
+                TokenType::UnsignedIntegerLiteral(0x1F),
+                TokenType::Comma,
+                TokenType::FloatLiteral(1.5)
+            ]
+        );
 
-        for i in 0..output.len() {
-            assert_eq!(output[i], expected[i]);
-        }
-    }
+        let strict: Vec<_> = DEFAULT_RADIX_PREFIXES
+            .into_iter()
+            .filter(|(_, numeric)| *numeric != NumericType::Float)
+            .collect();
 
-    #[test]
-    fn test_hex() {
-        let input = "0x2abcdef";
-        let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
+        let mut lexer = LexerBuilder::new()
+            .radix_prefixes(strict)
+            .build(Either::Left(f.clone()));
 
         assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::UnsignedIntegerLiteral(0x2abcdef),
-                2,
-                input.len() - 2,
+            lexer.process(),
+            Err(LexerError::UnknownRadixPrefix(TextRange::new(
+                Position::new(6, 0, 6),
+                Position::new(8, 0, 8),
                 Either::Left(f.clone())
-            )]
-        )
+            )))
+        );
     }
 
     #[test]
-    fn test_bin() {
-        let input = "0b01100110";
+    fn test_bare_zero() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
 
-        assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::UnsignedIntegerLiteral(0b01100110),
-                2,
-                input.len() - 2,
-                Either::Left(f.clone())
-            )]
-        )
-    }
+        for (input, len) in [("0", 1), ("00", 2), ("0 ", 1)] {
+            let f = f_man.new_file(String::new(), input.to_string());
 
-    #[test]
-    fn test_bin_64bits() {
-        let input = "0b1110011001100110011001100110011001100110011001100110011001100110";
-        let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
+            assert_eq!(
+                Lexer::tokenize(f.clone()),
+                Ok(vec![new_token!(
+                    TokenType::UnsignedIntegerLiteral(0),
+                    0,
+                    len,
+                    Either::Left(f.clone())
+                )]),
+                "{:?}",
+                input
+            );
+        }
+
+        let f = f_man.new_file(String::new(), "0".to_string());
 
         assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::UnsignedIntegerLiteral(
-                    0b1110011001100110011001100110011001100110011001100110011001100110
-                ),
-                2,
-                input.len() - 2,
+            Lexer::tokenize_with_default(f.clone(), NumericType::Hex),
+            Ok(vec![new_token!(
+                TokenType::UnsignedIntegerLiteral(0),
+                0,
+                1,
                 Either::Left(f.clone())
-            )]
-        )
+            )])
+        );
     }
 
     #[test]
-    fn test_bin_65bits() {
-        let input = "0b11100110011001100110011001100110011001100110011001100110011001101";
+    fn test_blank_input() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap_err();
 
-        assert_eq!(
-            output,
-            LexerError::InvalidBinaryLiteral(TextRange::new(
-                Position::new(3, 0, 3),
-                Position::new(input.len(), 0, input.len()),
-                Either::Left(f.clone())
-            ))
-        )
+        for input in ["", "  \t\n\r\n  \n", "# one\n/* two\n */\n\t# three"] {
+            let f = f_man.new_file(String::new(), input.to_string());
+
+            assert_eq!(Lexer::tokenize(f.clone()), Ok(Vec::new()));
+
+            let mut lexer = LexerBuilder::new()
+                .keep_comments(true)
+                .emit_newlines(true)
+                .build(Either::Left(f.clone()));
+
+            lexer.process().unwrap();
+
+            assert!(strip_trivia(lexer.into_tokens()).is_empty());
+
+            let mut lexer = LexerBuilder::new()
+                .emit_eof(true)
+                .build(Either::Left(f.clone()));
+
+            lexer.process().unwrap();
+
+            let tokens = lexer.into_tokens();
+
+            assert_eq!(tokens.len(), 1);
+            assert!(tokens[0].is_eof());
+            assert_eq!(tokens[0].lexeme().end().index(), input.chars().count());
+        }
     }
 
     #[test]
-    fn test_signed_int() {
-        let input = "0i-123";
+    fn test_validate() {
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
 
-        assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::SignedIntegerLiteral(-123),
-                2,
-                4,
-                Either::Left(f.clone())
-            )]
+        let f = f_man.new_file(String::new(), "START:\n\tldi $r0, 0x10\n\thalt".to_string());
+        assert!(Lexer::validate(f.clone()).is_empty());
+
+        let f = f_man.new_file(
+            String::new(),
+            "ldi $r0, 0x?? # ok\nhalt ?\nhalt".to_string(),
         );
+        let errors = Lexer::validate(f.clone());
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], LexerError::InvalidHexLiteral(_)));
+        assert!(matches!(
+            errors[1],
+            LexerError::UnexpectedCharacter('?', _, _)
+        ));
     }
 
     #[test]
-    fn test_float() {
-        let input = "0f-123.333333";
+    fn test_stream_stops_after_error() {
+        let input = "halt ? halt";
         let mut f_man = FileInfoManager::new();
         let f = f_man.new_file(String::new(), input.to_string());
-        let output = Lexer::tokenize(f.clone()).unwrap();
 
-        assert_eq!(
-            output,
-            vec![new_token!(
-                TokenType::FloatLiteral(-123.333333),
-                2,
-                input.len() - 2,
-                Either::Left(f.clone())
-            )]
-        );
+        let mut stream = Lexer::stream(f.clone());
+
+        assert!(stream.next().unwrap().is_ok());
+        assert!(stream.next().unwrap().is_err());
+        assert_eq!(stream.next(), None);
     }
 
+    #[cfg(feature = "with-serde")]
     #[test]
-    fn test_comment_eol() {
-        let input = "ldi 52, $r0 #452";
+    fn test_serde_round_trip() {
+        let input = "main:\n\tldi $r0, 0u52\n\tldf $rou, 0f1.5\n%import \"other.vsm\"";
         let mut f_man = FileInfoManager::new();
-        let f = f_man.new_file(String::new(), input.to_string());
+        let f = f_man.new_file(String::from("main.vsm"), input.to_string());
         let output = Lexer::tokenize(f.clone()).unwrap();
 
-        assert_eq!(
-            output,
-            vec![
-                new_token!(TokenType::Opcode(3), 0, 3, Either::Left(f.clone())),
-                new_token!(
-                    TokenType::UnsignedIntegerLiteral(52),
-                    4,
-                    2,
-                    Either::Left(f.clone())
-                ),
-                new_token!(TokenType::Comma, 6, 1, Either::Left(f.clone())),
-                new_token!(
-                    TokenType::Register(Register::R0),
-                    9,
-                    2,
-                    Either::Left(f.clone())
-                ),
-            ]
-        );
+        let json = serde_json::to_string(&output).unwrap();
+
+        // Only the id and name of the file are written, not its contents.
+        assert!(!json.contains("ldi $r0"));
+
+        let deserialized: Vec<Token> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(f_man.attach_tokens(deserialized), Some(output));
     }
 
     #[test]
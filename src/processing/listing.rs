@@ -0,0 +1,301 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use either::Either;
+
+use crate::processing::DEFAULT_SECTION;
+use crate::text_mapping::Source;
+use crate::token::{Token, TokenType};
+
+/// The bytes emitted for a single source line, and the address of the first of them within its
+/// section.
+struct ListedLine {
+    row: usize,
+    section: usize,
+    address: usize,
+    bytes: Vec<u8>,
+}
+
+/// Produces a listing of an assembled program, showing the address, emitted bytes and text of
+/// every source line.
+///
+/// `tokens` is the preprocessed token stream and `encoded` holds the bytes of each instruction,
+/// in the same order as the opcodes in `tokens`. Data directives are listed with the bytes they
+/// emit, `%org` moves the address forward and sections are placed one after another, so that
+/// addresses match the values of labels.
+/// Lines that emit nothing, such as labels and comments, are listed at the address of the next
+/// instruction with an empty byte column.
+pub fn generate_listing(tokens: &[Token], encoded: &[Vec<u8>]) -> String {
+    let mut sources: Vec<(Source, Vec<ListedLine>)> = Vec::new();
+    let mut instructions = encoded.iter();
+    // The name and location counter of each section, in the order they were first used
+    let mut sections = vec![(String::from(DEFAULT_SECTION), 0)];
+    let mut section = 0;
+    let mut tokens = tokens.iter();
+
+    while let Some(token) = tokens.next() {
+        let source = token.lexeme().source();
+
+        let index = match sources.iter().position(|(s, _)| s == source) {
+            Some(i) => i,
+            None => {
+                sources.push((source.clone(), Vec::new()));
+                sources.len() - 1
+            }
+        };
+
+        let bytes = match token.token_type() {
+            TokenType::Opcode(_) => match instructions.next() {
+                Some(b) => b.clone(),
+                None => break,
+            },
+            TokenType::Ascii | TokenType::Asciiz => match tokens.next().map(|t| t.token_type()) {
+                Some(TokenType::String(string)) => {
+                    let mut bytes = string.as_bytes().to_vec();
+
+                    if token.token_type() == TokenType::Asciiz {
+                        bytes.push(0);
+                    }
+
+                    bytes
+                }
+                _ => continue,
+            },
+            TokenType::Fill => {
+                let count = tokens.next().map(|t| t.token_type());
+                let value = tokens.next().map(|t| t.token_type());
+
+                match (count, value) {
+                    (
+                        Some(TokenType::UnsignedIntegerLiteral(count)),
+                        Some(TokenType::UnsignedIntegerLiteral(value)),
+                    ) => vec![value as u8; count as usize],
+                    _ => continue,
+                }
+            }
+            TokenType::Org => {
+                if let Some(TokenType::UnsignedIntegerLiteral(target)) =
+                    tokens.next().map(|t| t.token_type())
+                {
+                    let address = &mut sections[section].1;
+                    *address = (*address).max(target as usize);
+                }
+
+                continue;
+            }
+            TokenType::Section => {
+                if let Some(TokenType::String(name)) = tokens.next().map(|t| t.token_type()) {
+                    section = match sections.iter().position(|(s, _)| **s == *name) {
+                        Some(i) => i,
+                        None => {
+                            sections.push((String::from(&*name), 0));
+                            sections.len() - 1
+                        }
+                    };
+                }
+
+                continue;
+            }
+            _ => continue,
+        };
+
+        if bytes.is_empty() {
+            continue;
+        }
+
+        let row = token.lexeme().start().row();
+        let lines = &mut sources[index].1;
+        let address = &mut sections[section].1;
+
+        // Opcodes expanded from a macro report the rows of its body, so a row can be revisited
+        // after later rows have been listed.
+        match lines.iter_mut().find(|line| line.row == row) {
+            Some(line) => line.bytes.extend_from_slice(&bytes),
+            None => lines.push(ListedLine {
+                row,
+                section,
+                address: *address,
+                bytes: bytes.clone(),
+            }),
+        }
+
+        *address += bytes.len();
+    }
+
+    let mut bases = Vec::new();
+    let mut base = 0;
+
+    for (_, size) in &sections {
+        bases.push(base);
+        base += size;
+    }
+
+    for line in sources.iter_mut().flat_map(|(_, lines)| lines.iter_mut()) {
+        line.address += bases[line.section];
+    }
+
+    let width = sources
+        .iter()
+        .flat_map(|(_, lines)| lines.iter())
+        .map(|line| line.bytes.len() * 3)
+        .max()
+        .unwrap_or(0)
+        .saturating_sub(1);
+
+    let mut output = String::new();
+
+    for (source, lines) in &mut sources {
+        lines.sort_by_key(|line| line.row);
+
+        let texts: Vec<&str> = match source {
+            Either::Left(file) => (0..).map_while(|row| file.line_text(row)).collect(),
+            Either::Right(string) => string.as_str().lines().collect(),
+        };
+
+        let mut listed = lines.iter().peekable();
+        let mut address = 0;
+
+        for (row, text) in texts.iter().enumerate() {
+            let mut bytes = String::new();
+            let mut next_address = address;
+
+            if let Some(line) = listed.next_if(|line| line.row == row) {
+                address = line.address;
+                next_address = line.address + line.bytes.len();
+
+                for (i, byte) in line.bytes.iter().enumerate() {
+                    if i != 0 {
+                        bytes.push(' ');
+                    }
+
+                    write!(bytes, "{:02X}", byte).unwrap();
+                }
+            } else if let Some(line) = listed.peek() {
+                address = line.address;
+            }
+
+            let listed_line = format!(
+                "{:08X}  {:<width$}  {}",
+                address,
+                bytes,
+                text,
+                width = width
+            );
+            output.push_str(listed_line.trim_end());
+            output.push('\n');
+
+            address = next_address;
+        }
+    }
+
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use hashbrown::{HashMap, HashSet};
+
+    use crate::processing::{Lexer, PreProcessor};
+    use crate::text_mapping::FileInfoManager;
+
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            "root.asm".to_string(),
+            "START:\n    ldi $r0, 5 # five\n\n    halt".to_string(),
+        );
+        let tokens = Lexer::tokenize(f.clone()).unwrap();
+
+        let listing = generate_listing(&tokens, &[vec![0x03, 0x06, 0x05], vec![0x45]]);
+
+        assert_eq!(
+            listing,
+            concat!(
+                "00000000            START:\n",
+                "00000000  03 06 05      ldi $r0, 5 # five\n",
+                "00000003\n",
+                "00000003  45            halt\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_listing_macro() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            "root.asm".to_string(),
+            "%macro z\nhalt\n%end_macro\nldi $r0, 1\nz\nhalt".to_string(),
+        );
+        let mut tokens = HashMap::new();
+        tokens.insert(f.clone(), Lexer::tokenize(f.clone()).unwrap());
+        let tokens = PreProcessor::new(tokens, HashSet::new()).run(&f).unwrap();
+
+        let listing = generate_listing(&tokens, &[vec![0x03, 0x06, 0x01], vec![0x45], vec![0x45]]);
+
+        assert_eq!(
+            listing,
+            concat!(
+                "00000003            %macro z\n",
+                "00000003  45        halt\n",
+                "00000000            %end_macro\n",
+                "00000000  03 06 01  ldi $r0, 1\n",
+                "00000004            z\n",
+                "00000004  45        halt\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_listing_data() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            "root.asm".to_string(),
+            "%ascii \"hi\"\n%fill 2, 0xFF\nhalt".to_string(),
+        );
+        let mut tokens = HashMap::new();
+        tokens.insert(f.clone(), Lexer::tokenize(f.clone()).unwrap());
+        let tokens = PreProcessor::new(tokens, HashSet::new()).run(&f).unwrap();
+
+        let listing = generate_listing(&tokens, &[vec![0x45]]);
+
+        assert_eq!(
+            listing,
+            concat!(
+                "00000000  68 69  %ascii \"hi\"\n",
+                "00000002  FF FF  %fill 2, 0xFF\n",
+                "00000004  45     halt\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_listing_sections() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            "root.asm".to_string(),
+            "%section .data\n%ascii \"hi\"\n%section .text\nhalt".to_string(),
+        );
+        let mut tokens = HashMap::new();
+        tokens.insert(f.clone(), Lexer::tokenize(f.clone()).unwrap());
+        let tokens = PreProcessor::new(tokens, HashSet::new()).run(&f).unwrap();
+
+        let listing = generate_listing(&tokens, &[vec![0x45]]);
+
+        assert_eq!(
+            listing,
+            concat!(
+                "00000001         %section .data\n",
+                "00000001  68 69  %ascii \"hi\"\n",
+                "00000000         %section .text\n",
+                "00000000  45     halt\n",
+            )
+        );
+    }
+}
@@ -1,13 +1,29 @@
 mod assembler;
+mod expression;
+mod formatter;
+mod import_resolver;
 mod lexer;
+mod listing;
 mod parser;
 mod pre_processor;
 #[cfg(feature = "string_preprocessor")]
 mod pre_processor_string;
+mod token_buffer;
 
-pub use assembler::Assembler;
-pub use lexer::Lexer;
+pub use assembler::{
+    assemble, assemble_with_stats, symbol_table, Assembler, AssemblyStats, DEFAULT_SECTION,
+};
+pub use formatter::format_tokens;
+pub use import_resolver::ImportResolver;
+pub(crate) use lexer::MAX_HEX_DIGITS;
+pub use lexer::{
+    CommentStyle, Lexer, LexerBuilder, LexerCheckpoint, LexerStream, NumericType,
+    UnknownDirectivePolicy, DEFAULT_RADIX_PREFIXES,
+};
+pub use listing::generate_listing;
 pub use parser::Parser;
 pub use pre_processor::PreProcessor;
+pub(crate) use pre_processor::MAX_IMAGE_SIZE;
 #[cfg(feature = "string_preprocessor")]
 pub use pre_processor_string::StringPreProcessor;
+pub use token_buffer::TokenBuffer;
@@ -4,7 +4,8 @@ use vxl_iset::instruction::Instruction;
 use vxl_iset::instruction_arguments::{Address, Immediate, Register};
 
 use crate::error::ParserError;
-use crate::token::{Token, TokenType};
+use crate::text_mapping::TextRange;
+use crate::token::{Operator, Token, TokenType};
 
 type ParserResult<T> = Result<T, ParserError>;
 
@@ -34,6 +35,11 @@ impl Parser {
 
         let opcode = match opcode_token.token_type() {
             TokenType::Opcode(code) => code,
+            TokenType::RParen => {
+                return Err(ParserError::UnbalancedParenthesis(
+                    opcode_token.lexeme().clone(),
+                ))
+            }
             _ => return Err(ParserError::ExpectedOpcode(opcode_token)),
         };
 
@@ -99,7 +105,7 @@ impl Parser {
                 registers_vec.push(reg);
             } else if next == 1 {
                 // Immediate
-                tok = self.next(Some(&opcode_token))?;
+                tok = self.next_operand(&opcode_token)?;
 
                 let imm = match tok.token_type() {
                     TokenType::UnsignedIntegerLiteral(u) => Immediate::from(u),
@@ -116,7 +122,7 @@ impl Parser {
                 immediates_vec.push(imm);
             } else if next == 2 {
                 // Address
-                tok = self.next(Some(&opcode_token))?;
+                tok = self.next_operand(&opcode_token)?;
 
                 let add = match tok.token_type() {
                     TokenType::UnsignedIntegerLiteral(u) => u,
@@ -144,9 +150,107 @@ impl Parser {
             }
         }
 
+        // A trailing comma after the last operand is allowed
+        if i != 0 && self.tokens_iter.peek().is_some_and(|t| t.is_comma()) {
+            self.tokens_iter.next();
+        }
+
         return Ok((registers_vec, immediates_vec, addresses_vec));
     }
 
+    /// Reads an operand, folding a parenthesised integer expression into a single literal.
+    fn next_operand(&mut self, reference_token: &Token) -> ParserResult<Token> {
+        let open = self.next(Some(reference_token))?;
+
+        if !open.is_l_paren() {
+            return Ok(open);
+        }
+
+        let mut span = open.lexeme().clone();
+        let value = self.parenthesised_expression(&mut span)?;
+
+        if value >= 0 {
+            if let Ok(v) = u64::try_from(value) {
+                return Ok(Token::new(TokenType::UnsignedIntegerLiteral(v), span));
+            }
+        } else if let Ok(v) = i64::try_from(value) {
+            return Ok(Token::new(TokenType::SignedIntegerLiteral(v), span));
+        }
+
+        return Err(ParserError::InvalidOperandExpression(span));
+    }
+
+    /// Evaluates an expression up to and including its closing ')', extending `span` over it.
+    fn parenthesised_expression(&mut self, span: &mut TextRange) -> ParserResult<i128> {
+        let operand = self.expression_operand(span)?;
+        let value = self.fold_expression(operand, 0, span)?;
+
+        return match self.tokens_iter.next() {
+            Some(tok) if tok.is_r_paren() => {
+                Self::extend_span(span, &tok);
+
+                Ok(value)
+            }
+            _ => Err(ParserError::UnbalancedParenthesis(span.clone())),
+        };
+    }
+
+    fn expression_operand(&mut self, span: &mut TextRange) -> ParserResult<i128> {
+        let tok = match self.tokens_iter.next() {
+            Some(t) => t,
+            None => return Err(ParserError::UnbalancedParenthesis(span.clone())),
+        };
+
+        Self::extend_span(span, &tok);
+
+        return match tok.token_type() {
+            TokenType::UnsignedIntegerLiteral(u) => Ok(u as i128),
+            TokenType::SignedIntegerLiteral(i) => Ok(i as i128),
+            TokenType::Operator(Operator::Subtract) => Ok(-self.expression_operand(span)?),
+            TokenType::LParen => self.parenthesised_expression(span),
+            _ => Err(ParserError::InvalidOperandExpression(span.clone())),
+        };
+    }
+
+    /// Applies every operator binding at least as tightly as `min_precedence` to `lhs`.
+    fn fold_expression(
+        &mut self,
+        mut lhs: i128,
+        min_precedence: u8,
+        span: &mut TextRange,
+    ) -> ParserResult<i128> {
+        while let Some(TokenType::Operator(operator)) =
+            self.tokens_iter.peek().map(|t| t.token_type())
+        {
+            if operator.precedence() < min_precedence {
+                break;
+            }
+
+            let tok = self.tokens_iter.next().unwrap();
+            Self::extend_span(span, &tok);
+
+            let operand = self.expression_operand(span)?;
+            let rhs = self.fold_expression(operand, operator.precedence() + 1, span)?;
+
+            if rhs == 0 && (operator == Operator::Divide || operator == Operator::Remainder) {
+                return Err(ParserError::DivisionByZero(span.clone()));
+            }
+
+            lhs = match operator.apply(lhs, rhs) {
+                Some(v) => v,
+                None => return Err(ParserError::InvalidOperandExpression(span.clone())),
+            };
+        }
+
+        return Ok(lhs);
+    }
+
+    fn extend_span(span: &mut TextRange, tok: &Token) {
+        if let Some(merged) = span.merge(tok.lexeme()) {
+            *span = merged;
+        }
+    }
+
     fn next(&mut self, reference_token: Option<&Token>) -> ParserResult<Token> {
         if let Some(tok) = self.tokens_iter.next() {
             return Ok(tok);
@@ -159,3 +263,73 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::processing::Lexer;
+
+    use super::*;
+
+    fn parser(input: &str) -> Parser {
+        return Parser::with_tokens(Lexer::tokenize_string(input.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_parenthesised_operand() {
+        let mut parser = parser("(4 + 1) (2 * (3 - 1) << 1) 7");
+        let reference = Lexer::tokenize_string("halt".to_string())
+            .unwrap()
+            .remove(0);
+
+        let sum = parser.next_operand(&reference).unwrap();
+        assert_eq!(sum.token_type(), TokenType::UnsignedIntegerLiteral(5));
+        assert_eq!(sum.lexeme().string(), "(4 + 1)");
+
+        assert_eq!(
+            parser.next_operand(&reference).unwrap().token_type(),
+            TokenType::UnsignedIntegerLiteral(8)
+        );
+        assert_eq!(
+            parser.next_operand(&reference).unwrap().token_type(),
+            TokenType::UnsignedIntegerLiteral(7)
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_parenthesis() {
+        let reference = Lexer::tokenize_string("halt".to_string())
+            .unwrap()
+            .remove(0);
+
+        match parser("(4 + (1 * 2)").next_operand(&reference) {
+            Err(ParserError::UnbalancedParenthesis(range)) => {
+                assert_eq!(range.string(), "(4 + (1 * 2)");
+            }
+            other => panic!("Expected an unbalanced parenthesis, found {:?}", other),
+        }
+
+        assert!(matches!(
+            parser(")").parse(),
+            Err(ParserError::UnbalancedParenthesis(_))
+        ));
+    }
+
+    #[test]
+    fn test_trailing_comma() {
+        let encode = |instructions: Vec<Instruction>| -> Vec<Vec<u8>> {
+            return instructions.into_iter().map(|i| i.into()).collect();
+        };
+
+        let with_comma = parser("malloc $r0, $r1,\njmp 0,\nhalt").parse().unwrap();
+        let without = parser("malloc $r0, $r1\njmp 0\nhalt").parse().unwrap();
+
+        assert_eq!(encode(with_comma), encode(without));
+
+        assert!(matches!(
+            parser("halt,").parse(),
+            Err(ParserError::ExpectedOpcode(_))
+        ));
+    }
+}
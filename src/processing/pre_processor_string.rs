@@ -1,10 +1,16 @@
 use core::iter::Peekable;
 
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use hashbrown::{HashMap, HashSet};
 
-use crate::error::PreProcessorError;
+use crate::error::{PreProcessorError, PreProcessorWarning};
+use crate::processing::assembler::encoded_length;
+use crate::processing::expression::{evaluate_constant, evaluate_constant_from};
+use crate::processing::pre_processor::{Macro, MAX_IMAGE_SIZE, MAX_MACRO_DEPTH};
+use crate::processing::DEFAULT_SECTION;
 use crate::token::{Token, TokenType};
 
 #[derive(Debug)]
@@ -12,6 +18,21 @@ pub struct StringPreProcessor {
     tokens: Vec<Token>,
     constants: HashMap<String, Token>,
     flags: HashSet<String>,
+    macros: HashMap<String, Macro>,
+    macro_depth: usize,
+    current_label: Option<String>,
+    entry: Option<Token>,
+    globals: HashSet<String>,
+    externs: HashSet<String>,
+    local_scopes: HashMap<usize, String>,
+    warnings: Vec<PreProcessorWarning>,
+    labels: BTreeMap<String, u64>,
+    label_sections: HashMap<String, String>,
+    current_section: String,
+    /// The sections in the order they were first used, starting with `.text`.
+    sections: Vec<String>,
+    section_counters: HashMap<String, usize>,
+    /// The location counter, in encoded bytes from the start of the current section.
     opcode_count: usize,
     primary_output: Vec<Token>,
     secondary_output: Vec<Token>,
@@ -25,6 +46,19 @@ impl StringPreProcessor {
             tokens,
             constants: HashMap::new(),
             flags,
+            macros: HashMap::new(),
+            macro_depth: 0,
+            current_label: None,
+            entry: None,
+            globals: HashSet::new(),
+            externs: HashSet::new(),
+            local_scopes: HashMap::new(),
+            warnings: Vec::new(),
+            labels: BTreeMap::new(),
+            label_sections: HashMap::new(),
+            current_section: String::from(DEFAULT_SECTION),
+            sections: vec![String::from(DEFAULT_SECTION)],
+            section_counters: HashMap::new(),
             opcode_count: 0,
             primary_output: Vec::new(),
             secondary_output: Vec::new(),
@@ -60,6 +94,54 @@ impl StringPreProcessor {
         return Ok((self.secondary_output, offset));
     }
 
+    /// Runs both passes, returning the address of the label declared with `%entry`, or 0 if no
+    /// entry point was declared.
+    pub fn run_with_entry(mut self) -> PreProcessorResult<(Vec<Token>, u64)> {
+        self.primary_process()?;
+        self.secondary_process()?;
+
+        let offset = self.entry_point().unwrap_or(0);
+
+        return Ok((self.secondary_output, offset));
+    }
+
+    /// The warnings raised so far.
+    pub fn warnings(&self) -> &[PreProcessorWarning] {
+        return &self.warnings;
+    }
+
+    /// Every label defined so far and its address, with local labels qualified by the label
+    /// they are scoped to, as in `main.loop`.
+    pub fn labels(&self) -> &BTreeMap<String, u64> {
+        return &self.labels;
+    }
+
+    /// The section a label was defined in.
+    pub fn label_section(&self, label: &str) -> Option<&str> {
+        return self.label_sections.get(label).map(|s| s.as_str());
+    }
+
+    /// The labels exported with `%global`.
+    pub fn globals(&self) -> &HashSet<String> {
+        return &self.globals;
+    }
+
+    /// The symbols declared with `%extern`, references to these are left unresolved if they are
+    /// not defined.
+    pub fn externs(&self) -> &HashSet<String> {
+        return &self.externs;
+    }
+
+    /// The address of the label declared with `%entry`, available once both passes have run.
+    pub fn entry_point(&self) -> Option<u64> {
+        let entry = self.entry.as_ref()?;
+
+        return match self.constants.get(&entry.lexeme().string())?.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) => Some(v),
+            _ => None,
+        };
+    }
+
     /// Replaces most of the assembler directives with their text.
     ///
     /// Processes the definitions of constants and if statements in the order they are defined.
@@ -69,18 +151,40 @@ impl StringPreProcessor {
             .into_iter()
             .peekable();
 
+        return self.process_tokens(&mut tokens);
+    }
+
+    fn process_tokens<T: Iterator<Item = Token>>(
+        &mut self,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
         while let Some(token) = tokens.next() {
             match token.token_type() {
-                TokenType::Identifier => self.handle_identifier(token, &mut tokens)?,
-                TokenType::Constant => self.handle_constant_definition(token, &mut tokens)?,
+                TokenType::Comment => (),
+                TokenType::RawDirective => self.handle_raw_directive(token, tokens),
+                TokenType::Identifier => self.handle_identifier(token, tokens)?,
+                TokenType::LocalLabel => self.handle_local_label(token, tokens)?,
+                TokenType::Constant => self.handle_constant_definition(token, tokens)?,
                 TokenType::Import => return Err(PreProcessorError::ImportNotPermitted(token)),
-                TokenType::If => self.handle_if(token, &mut tokens)?,
+                TokenType::If | TokenType::Ifdef | TokenType::Ifndef => {
+                    self.handle_if(token, tokens)?
+                }
+                TokenType::Elif => return Err(PreProcessorError::UnexpectedElif(token)),
                 TokenType::Else => return Err(PreProcessorError::UnexpectedElse(token)),
                 TokenType::Endif => return Err(PreProcessorError::UnexpectedEndif(token)),
-                TokenType::Repeat => self.handle_repeat(token, &mut tokens)?,
+                TokenType::Repeat => self.handle_repeat(token, tokens)?,
                 TokenType::EndRepeat => return Err(PreProcessorError::UnexpectedEndRepeat(token)),
-                TokenType::Opcode(_) => {
-                    self.opcode_count += 1;
+                TokenType::Macro => self.handle_macro_definition(token, tokens)?,
+                TokenType::EndMacro => return Err(PreProcessorError::UnexpectedEndMacro(token)),
+                TokenType::Ascii | TokenType::Asciiz => self.handle_string_data(token, tokens)?,
+                TokenType::Fill => self.handle_fill(token, tokens)?,
+                TokenType::Org => self.handle_org(token, tokens)?,
+                TokenType::Section => self.handle_section(token, tokens)?,
+                TokenType::ErrorDirective => self.handle_error_directive(token, tokens)?,
+                TokenType::Entry => self.handle_entry(token, tokens)?,
+                TokenType::Global | TokenType::Extern => self.handle_visibility(token, tokens)?,
+                TokenType::Opcode(code) => {
+                    self.opcode_count += encoded_length(code);
                     self.primary_output.push(token);
                 }
                 _ => {
@@ -93,12 +197,42 @@ impl StringPreProcessor {
     }
 
     pub fn secondary_process(&mut self) -> PreProcessorResult<()> {
+        if let Some(entry) = &self.entry {
+            if !self.constants.contains_key(&entry.lexeme().string()) {
+                return Err(PreProcessorError::UndefinedLabel(entry.clone()));
+            }
+        }
+
+        self.add_section_bases();
+
         let primary_output = core::mem::replace(&mut self.primary_output, Vec::new());
 
-        for token in primary_output.into_iter() {
+        for (i, token) in primary_output.into_iter().enumerate() {
             match token.token_type() {
                 TokenType::Identifier => {
-                    if let Some(cons) = self.constants.get(&token.lexeme().string()) {
+                    let name = token.lexeme().string();
+
+                    if let Some(cons) = self.constants.get(&name) {
+                        if cons.is_string() {
+                            return Err(PreProcessorError::StringConstantAsNumber(token));
+                        }
+
+                        self.secondary_output.push(cons.clone());
+                    } else if self.externs.contains(&name) {
+                        // Left for the symbol to be resolved once it is linked.
+                        self.secondary_output.push(token);
+                    } else {
+                        return Err(PreProcessorError::UndefinedLabel(token));
+                    }
+                }
+                TokenType::Comment => (),
+                TokenType::LocalLabel => {
+                    let cons = match self.local_scopes.get(&i) {
+                        Some(name) => self.constants.get(name),
+                        None => None,
+                    };
+
+                    if let Some(cons) = cons {
                         self.secondary_output.push(cons.clone());
                     } else {
                         return Err(PreProcessorError::UndefinedLabel(token));
@@ -113,6 +247,43 @@ impl StringPreProcessor {
         return Ok(());
     }
 
+    /// Moves every label forward by the size of the sections laid out before its own, as the
+    /// assembler places sections one after another in the order they were first used.
+    fn add_section_bases(&mut self) {
+        let mut bases = HashMap::new();
+        let mut base = 0;
+
+        for name in &self.sections {
+            bases.insert(name.clone(), base as u64);
+
+            if *name == self.current_section {
+                base += self.opcode_count;
+            } else {
+                base += self.section_counters.get(name).copied().unwrap_or(0);
+            }
+        }
+
+        for (label, section) in &self.label_sections {
+            let base = match bases.get(section) {
+                Some(b) if *b != 0 => *b,
+                _ => continue,
+            };
+
+            if let Some(address) = self.labels.get_mut(label) {
+                *address += base;
+            }
+
+            if let Some(constant) = self.constants.get_mut(label) {
+                if let TokenType::UnsignedIntegerLiteral(address) = constant.token_type() {
+                    *constant = Token::new(
+                        TokenType::UnsignedIntegerLiteral(address + base),
+                        constant.lexeme().clone(),
+                    );
+                }
+            }
+        }
+    }
+
     pub fn primary_output(&self) -> &Vec<Token> {
         return &self.primary_output;
     }
@@ -137,6 +308,18 @@ impl StringPreProcessor {
                 TokenType::Colon => {
                     tokens.next();
 
+                    if let Some(previous) = self.constants.get(&str_ident) {
+                        return Err(PreProcessorError::DuplicateLabel(
+                            constant_identifier,
+                            previous.clone(),
+                        ));
+                    }
+
+                    self.current_label = Some(str_ident.clone());
+                    self.labels
+                        .insert(str_ident.clone(), self.opcode_count as u64);
+                    self.label_sections
+                        .insert(str_ident.clone(), self.current_section.clone());
                     self.constants.insert(
                         str_ident,
                         Token::new(
@@ -151,7 +334,22 @@ impl StringPreProcessor {
             }
         }
 
-        if let Some(v) = self.constants.get(&str_ident) {
+        if let Some(definition) = self.macros.get(&str_ident) {
+            let definition = definition.clone();
+
+            return self.handle_macro_call(constant_identifier, definition, tokens);
+        }
+
+        if self.label_sections.contains_key(&str_ident) {
+            // Left for the second pass, once the sections the label is placed after are sized
+            self.primary_output.push(constant_identifier);
+        } else if let Some(v) = self.constants.get(&str_ident) {
+            if v.is_string() {
+                return Err(PreProcessorError::StringConstantAsNumber(
+                    constant_identifier,
+                ));
+            }
+
             self.primary_output.push(v.clone());
         } else {
             self.primary_output.push(constant_identifier);
@@ -160,36 +358,172 @@ impl StringPreProcessor {
         return Ok(());
     }
 
-    fn handle_constant_definition<T: Iterator<Item = Token>>(
+    fn handle_local_label<T: Iterator<Item = Token>>(
         &mut self,
-        constant_identifier: Token,
-        tokens: &mut T,
+        label: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        let scoped_name = self.scoped_local_label(&label);
+
+        if tokens.peek().is_some() && tokens.peek().unwrap().is_colon() {
+            tokens.next();
+
+            self.labels
+                .insert(scoped_name.clone(), self.opcode_count as u64);
+            self.label_sections
+                .insert(scoped_name.clone(), self.current_section.clone());
+            self.constants.insert(
+                scoped_name,
+                Token::new(
+                    TokenType::UnsignedIntegerLiteral(self.opcode_count as u64),
+                    label.lexeme().clone(),
+                ),
+            );
+        } else {
+            self.local_scopes
+                .insert(self.primary_output.len(), scoped_name);
+            self.primary_output.push(label);
+        }
+
+        return Ok(());
+    }
+
+    /// The name a local label is stored under, qualified by the label it is scoped to.
+    fn scoped_local_label(&self, label: &Token) -> String {
+        let mut name = self.current_label.clone().unwrap_or_default();
+        name.push_str(&label.lexeme().string());
+
+        return name;
+    }
+
+    fn handle_macro_definition<T: Iterator<Item = Token>>(
+        &mut self,
+        macro_identifier: Token,
+        tokens: &mut Peekable<T>,
     ) -> PreProcessorResult<()> {
         if let Some(name) = tokens.next() {
             if !name.is_identifier() {
-                return Err(PreProcessorError::InvalidConstantName(name));
+                return Err(PreProcessorError::InvalidMacroName(name));
             }
 
-            if let Some(value) = tokens.next() {
-                if !value.is_unsigned_integer()
-                    && !value.is_signed_integer()
-                    && !value.is_float()
-                    && !value.is_string()
-                {
-                    return Err(PreProcessorError::InvalidConstantValue(
-                        constant_identifier,
-                        value,
-                    ));
+            // The parameters are the identifiers on the same line as the name, the body starts
+            // on the next line.
+            let row = name.lexeme().start().row();
+            let mut parameters = Vec::new();
+
+            while tokens.peek().is_some()
+                && tokens.peek().unwrap().is_identifier()
+                && tokens.peek().unwrap().lexeme().start().row() == row
+            {
+                parameters.push(tokens.next().unwrap().lexeme().string());
+            }
+
+            let mut body = Vec::new();
+            let mut terminated = false;
+
+            while let Some(next) = tokens.next() {
+                if next.is_end_macro() {
+                    terminated = true;
+                    break;
+                } else if next.is_macro() {
+                    return Err(PreProcessorError::ForbiddenDirective(next));
+                } else {
+                    body.push(next);
                 }
+            }
+
+            if !terminated {
+                return Err(PreProcessorError::UnterminatedMacro(macro_identifier));
+            }
+
+            self.macros
+                .insert(name.lexeme().string(), Macro { parameters, body });
+
+            return Ok(());
+        } else {
+            return Err(PreProcessorError::ExpectedIdentifierFoundEOF(
+                macro_identifier,
+            ));
+        }
+    }
+
+    fn handle_macro_call<T: Iterator<Item = Token>>(
+        &mut self,
+        call_identifier: Token,
+        definition: Macro,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return Err(PreProcessorError::MacroRecursionLimit(call_identifier));
+        }
 
-                self.constants.insert(name.lexeme().string(), value);
+        let mut arguments = Vec::with_capacity(definition.parameters.len());
+
+        for i in 0..definition.parameters.len() {
+            if i != 0 {
+                match tokens.next() {
+                    Some(comma) if comma.is_comma() => (),
+                    Some(found) => {
+                        return Err(PreProcessorError::ExpectedCommaFound(
+                            call_identifier,
+                            found,
+                        ))
+                    }
+                    None => {
+                        return Err(PreProcessorError::ExpectedMacroArgumentFoundEOF(
+                            call_identifier,
+                        ))
+                    }
+                }
+            }
 
-                return Ok(());
+            if let Some(argument) = tokens.next() {
+                arguments.push(argument);
             } else {
-                return Err(PreProcessorError::ExpectedUnsignedIntegerFoundEOF(
-                    constant_identifier,
+                return Err(PreProcessorError::ExpectedMacroArgumentFoundEOF(
+                    call_identifier,
                 ));
             }
+        }
+
+        let expanded: Vec<Token> = definition
+            .body
+            .into_iter()
+            .map(|token| {
+                if token.is_identifier() {
+                    let name = token.lexeme().string();
+
+                    if let Some(i) = definition.parameters.iter().position(|p| *p == name) {
+                        return arguments[i].clone();
+                    }
+                }
+
+                return token;
+            })
+            .collect();
+
+        self.macro_depth += 1;
+        let result = self.process_tokens(&mut expanded.into_iter().peekable());
+        self.macro_depth -= 1;
+
+        return result;
+    }
+
+    fn handle_constant_definition<T: Iterator<Item = Token>>(
+        &mut self,
+        constant_identifier: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        if let Some(name) = tokens.next() {
+            if !name.is_identifier() {
+                return Err(PreProcessorError::InvalidConstantName(name));
+            }
+
+            let value = evaluate_constant(&constant_identifier, tokens, &self.constants)?;
+
+            self.constants.insert(name.lexeme().string(), value);
+
+            return Ok(());
         } else {
             return Err(PreProcessorError::ExpectedIdentifierFoundEOF(
                 constant_identifier,
@@ -197,148 +531,457 @@ impl StringPreProcessor {
         }
     }
 
-    fn handle_repeat<T: Iterator<Item = Token>>(
+    /// Passes on `%ascii` or `%asciiz` with its string, which may be named by a string constant.
+    ///
+    /// The location counter moves past each byte of the string, and the terminating zero of
+    /// `%asciiz`.
+    fn handle_string_data<T: Iterator<Item = Token>>(
         &mut self,
-        repeat_identifier: Token,
+        directive: Token,
         tokens: &mut T,
     ) -> PreProcessorResult<()> {
         if let Some(next) = tokens.next() {
-            let times = match next.token_type() {
-                TokenType::UnsignedIntegerLiteral(v) => v,
-                _ => {
-                    return Err(PreProcessorError::ExpectedUnsignedIntegerFound(
-                        repeat_identifier,
-                        next,
-                    ))
-                }
+            let string = match next.token_type() {
+                TokenType::String(_) => next,
+                TokenType::Identifier => match self.constants.get(&next.lexeme().string()) {
+                    Some(v) if v.is_string() => v.clone(),
+                    Some(_) => {
+                        return Err(PreProcessorError::NumericConstantAsString(directive, next))
+                    }
+                    None => return Err(PreProcessorError::ExpectedStringFound(directive, next)),
+                },
+                _ => return Err(PreProcessorError::ExpectedStringFound(directive, next)),
             };
 
-            let mut cache = Vec::new();
+            if let TokenType::String(value) = string.token_type() {
+                self.opcode_count += value.len();
+            }
 
-            let mut terminated = false;
+            if directive.token_type() == TokenType::Asciiz {
+                self.opcode_count += 1;
+            }
 
-            let mut running_opcode_count = 0;
+            self.primary_output.push(directive);
+            self.primary_output.push(string);
 
-            while let Some(next) = tokens.next() {
-                if next.is_end_repeat() {
-                    terminated = true;
-                    break;
-                } else if next.is_directive() && !next.is_identifier() {
-                    return Err(PreProcessorError::ForbiddenDirective(next));
-                } else {
-                    if next.is_opcode() {
-                        running_opcode_count += 1;
-                    }
+            return Ok(());
+        } else {
+            return Err(PreProcessorError::ExpectedStringFoundEOF(directive));
+        }
+    }
 
-                    cache.push(next);
-                }
+    /// Validates `%fill count, value` and passes it on with both operands folded to literals,
+    /// moving the location counter past the filled bytes.
+    ///
+    /// The count may be any unsigned expression that keeps the output within `MAX_IMAGE_SIZE`,
+    /// while the value must fit in a byte.
+    fn handle_fill<T: Iterator<Item = Token>>(
+        &mut self,
+        directive: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        let count = evaluate_constant(&directive, tokens, &self.constants)?;
+
+        if !count.is_unsigned_integer() {
+            return Err(PreProcessorError::ExpectedUnsignedIntegerFound(
+                directive, count,
+            ));
+        }
+
+        match tokens.next() {
+            Some(t) if t.is_comma() => (),
+            Some(t) => return Err(PreProcessorError::ExpectedCommaFound(directive, t)),
+            None => {
+                return Err(PreProcessorError::ExpectedUnsignedIntegerFoundEOF(
+                    directive,
+                ))
             }
+        }
 
-            if !terminated {
-                return Err(PreProcessorError::UnterminatedRepeat(repeat_identifier));
+        let value = evaluate_constant(&directive, tokens, &self.constants)?;
+
+        match value.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) if v <= u8::MAX as u64 => (),
+            TokenType::UnsignedIntegerLiteral(_) => {
+                return Err(PreProcessorError::FillValueOutOfRange(directive, value))
+            }
+            _ => {
+                return Err(PreProcessorError::ExpectedUnsignedIntegerFound(
+                    directive, value,
+                ))
             }
+        }
 
-            for i in 0..times {
-                if i == times - 1 {
-                    self.primary_output.append(&mut cache)
-                } else {
-                    self.primary_output.append(&mut cache.clone())
-                }
+        if let TokenType::UnsignedIntegerLiteral(n) = count.token_type() {
+            let end = self.opcode_count.saturating_add(n as usize);
+
+            if end > MAX_IMAGE_SIZE {
+                return Err(PreProcessorError::ImageTooLarge(count, end));
             }
 
-            self.opcode_count += (running_opcode_count * times) as usize;
+            self.opcode_count = end;
+        }
 
-            return Ok(());
-        } else {
-            return Err(PreProcessorError::ExpectedUnsignedIntegerFoundEOF(
-                repeat_identifier,
+        self.primary_output.push(directive);
+        self.primary_output.push(count);
+        self.primary_output.push(value);
+
+        return Ok(());
+    }
+
+    /// Moves the location counter forward to the operand of `%org`, so that labels defined
+    /// after it resolve relative to the new address.
+    fn handle_org<T: Iterator<Item = Token>>(
+        &mut self,
+        directive: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        let address = evaluate_constant(&directive, tokens, &self.constants)?;
+
+        let target = match address.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) => v as usize,
+            _ => {
+                return Err(PreProcessorError::ExpectedUnsignedIntegerFound(
+                    directive, address,
+                ))
+            }
+        };
+
+        if target < self.opcode_count {
+            return Err(PreProcessorError::OrgMovesBackward(
+                address,
+                self.opcode_count,
             ));
         }
+
+        if target > MAX_IMAGE_SIZE {
+            return Err(PreProcessorError::ImageTooLarge(address, target));
+        }
+
+        self.opcode_count = target;
+        self.primary_output.push(directive);
+        self.primary_output.push(address);
+
+        return Ok(());
     }
 
-    fn handle_if<T: Iterator<Item = Token>>(
+    /// Switches to the section named by `%section`, saving the location counter of the current
+    /// section and resuming the new section's own.
+    ///
+    /// The name is passed on as a string, so that a name like `.data` is not taken for a label.
+    fn handle_section<T: Iterator<Item = Token>>(
         &mut self,
-        if_identifier: Token,
+        directive: Token,
         tokens: &mut T,
     ) -> PreProcessorResult<()> {
-        if let Some(flag) = tokens.next() {
-            if !flag.is_identifier() {
-                return Err(PreProcessorError::ExpectedIdentifierFlagFound(
-                    if_identifier,
-                    flag,
+        let name = match tokens.next() {
+            Some(t) if t.is_identifier() || t.is_local_label() => t,
+            Some(t) => return Err(PreProcessorError::ExpectedIdentifierFound(directive, t)),
+            None => return Err(PreProcessorError::ExpectedIdentifierFoundEOF(directive)),
+        };
+
+        let name_str = name.lexeme().string();
+        let previous = core::mem::replace(&mut self.current_section, name_str.clone());
+
+        self.section_counters.insert(previous, self.opcode_count);
+
+        if !self.sections.contains(&name_str) {
+            self.sections.push(name_str.clone());
+        }
+
+        self.opcode_count = self.section_counters.get(&name_str).copied().unwrap_or(0);
+
+        self.primary_output.push(directive);
+        self.primary_output.push(Token::new(
+            TokenType::String(name_str.as_str().into()),
+            name.lexeme().clone(),
+        ));
+
+        return Ok(());
+    }
+
+    fn handle_entry<T: Iterator<Item = Token>>(
+        &mut self,
+        entry_identifier: Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<()> {
+        if self.entry.is_some() {
+            return Err(PreProcessorError::DuplicateEntry(entry_identifier));
+        }
+
+        self.entry = Some(Self::expect_identifier(entry_identifier, tokens)?);
+
+        return Ok(());
+    }
+
+    fn handle_visibility<T: Iterator<Item = Token>>(
+        &mut self,
+        directive: Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<()> {
+        let is_global = directive.is_global();
+        let name = Self::expect_identifier(directive, tokens)?;
+
+        if is_global {
+            self.globals.insert(name.lexeme().string());
+        } else {
+            self.externs.insert(name.lexeme().string());
+        }
+
+        return Ok(());
+    }
+
+    /// Reads the identifier operand of a directive.
+    fn expect_identifier<T: Iterator<Item = Token>>(
+        directive: Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<Token> {
+        if let Some(identifier) = tokens.next() {
+            if !identifier.is_identifier() {
+                return Err(PreProcessorError::ExpectedIdentifierFound(
+                    directive, identifier,
                 ));
             }
 
-            let keep = self.flags.contains(&flag.lexeme().string());
+            return Ok(identifier);
+        } else {
+            return Err(PreProcessorError::ExpectedIdentifierFoundEOF(directive));
+        }
+    }
 
-            let mut cache = Vec::new();
+    fn handle_error_directive<T: Iterator<Item = Token>>(
+        &mut self,
+        error_identifier: Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<()> {
+        if let Some(next) = tokens.next() {
+            return match next.token_type() {
+                TokenType::String(message) => Err(PreProcessorError::UserError(
+                    error_identifier.lexeme().clone(),
+                    message,
+                )),
+                _ => Err(PreProcessorError::ExpectedStringFound(
+                    error_identifier,
+                    next,
+                )),
+            };
+        } else {
+            return Err(PreProcessorError::ExpectedStringFoundEOF(error_identifier));
+        }
+    }
 
-            let mut terminated = false;
-            let mut else_token = None;
+    /// Skips a directive meant for another tool, along with its operands on the same line.
+    fn handle_raw_directive<T: Iterator<Item = Token>>(
+        &mut self,
+        directive: Token,
+        tokens: &mut Peekable<T>,
+    ) {
+        let row = directive.lexeme().start().row();
+
+        while tokens
+            .peek()
+            .is_some_and(|t| t.lexeme().start().row() == row)
+        {
+            tokens.next();
+        }
+    }
 
-            while let Some(next) = tokens.next() {
-                if next.is_end_if() {
-                    terminated = true;
-                    break;
-                } else if next.is_else() {
-                    else_token = Some(next);
-                    break;
-                } else if next.is_directive() && !next.is_identifier() {
-                    return Err(PreProcessorError::ForbiddenDirective(next));
-                } else {
-                    if keep {
-                        if next.is_opcode() {
-                            self.opcode_count += 1;
-                        }
+    fn handle_repeat<T: Iterator<Item = Token>>(
+        &mut self,
+        repeat_identifier: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<()> {
+        let block = self.expand_repeat(repeat_identifier, tokens)?;
+
+        return self.process_tokens(&mut block.into_iter().peekable());
+    }
+
+    /// Reads a repeat block up to its `%end_repeat` and returns its tokens duplicated by the
+    /// count. Nested repeats are expanded in place with their own counts.
+    ///
+    /// An identifier followed by the count, as in `%repeat i 4`, names a loop variable that is
+    /// replaced by the iteration index within the block.
+    fn expand_repeat<T: Iterator<Item = Token>>(
+        &mut self,
+        repeat_identifier: Token,
+        tokens: &mut Peekable<T>,
+    ) -> PreProcessorResult<Vec<Token>> {
+        let first = match tokens.next() {
+            Some(t) => t,
+            None => {
+                return Err(PreProcessorError::ExpectedUnsignedIntegerFoundEOF(
+                    repeat_identifier,
+                ))
+            }
+        };
+
+        // A variable name is followed by the count on the same line, whereas a count given by a
+        // constant may be followed by a body starting with an identifier, such as a macro call.
+        let has_variable = first.is_identifier()
+            && tokens.peek().is_some_and(|t| {
+                t.lexeme().start().row() == first.lexeme().start().row()
+                    && (t.is_unsigned_integer()
+                        || t.is_signed_integer()
+                        || t.is_identifier()
+                        || t.is_l_paren())
+            });
+
+        let (variable, count) = if has_variable {
+            let count = evaluate_constant(&repeat_identifier, tokens, &self.constants)?;
+
+            (Some(first.lexeme().string()), count)
+        } else {
+            let count =
+                evaluate_constant_from(&repeat_identifier, first.clone(), tokens, &self.constants)?;
+
+            (None, count)
+        };
+
+        if let Some(name) = &variable {
+            if self.constants.contains_key(name) {
+                self.warnings
+                    .push(PreProcessorWarning::ShadowedConstant(first));
+            }
+        }
+
+        let times = match count.token_type() {
+            TokenType::UnsignedIntegerLiteral(v) => v,
+            _ => {
+                return Err(PreProcessorError::ExpectedUnsignedIntegerFound(
+                    repeat_identifier,
+                    count,
+                ))
+            }
+        };
+
+        let mut cache = Vec::new();
+
+        loop {
+            let next = match tokens.next() {
+                Some(t) => t,
+                None => return Err(PreProcessorError::UnterminatedRepeat(repeat_identifier)),
+            };
+
+            if next.is_end_repeat() {
+                break;
+            } else if next.is_repeat() {
+                cache.append(&mut self.expand_repeat(next, tokens)?);
+            } else if next.is_preprocessor_directive() {
+                return Err(PreProcessorError::ForbiddenDirective(next));
+            } else {
+                cache.push(next);
+            }
+        }
 
-                        cache.push(next);
-                    }
+        let mut output = Vec::new();
+
+        for i in 0..times {
+            output.extend(cache.iter().map(|t| match &variable {
+                Some(name) if t.is_identifier() && &t.lexeme().string() == name => {
+                    Token::new(TokenType::UnsignedIntegerLiteral(i), t.lexeme().clone())
+                }
+                _ => t.clone(),
+            }));
+        }
+
+        return Ok(output);
+    }
+
+    fn handle_if<T: Iterator<Item = Token>>(
+        &mut self,
+        if_identifier: Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<()> {
+        let mut keep = self.read_condition(&if_identifier, tokens)?;
+
+        // Whether any arm of the chain has been taken, later arms are skipped once one has.
+        let mut matched = keep;
+
+        let mut cache = Vec::new();
+
+        let mut terminated = false;
+        let mut else_token: Option<Token> = None;
+
+        while let Some(next) = tokens.next() {
+            if next.is_end_if() {
+                terminated = true;
+                break;
+            } else if next.is_elif() && else_token.is_none() {
+                let condition = self.read_condition(&next, tokens)?;
+
+                keep = !matched && condition;
+                matched = matched || condition;
+            } else if next.is_else() && else_token.is_none() {
+                keep = !matched;
+                matched = true;
+                else_token = Some(next);
+            } else if next.is_error_directive() {
+                // Only raised from the branch that is kept, the operand of a skipped one is
+                // dropped along with the rest of the branch.
+                if keep {
+                    return self.handle_error_directive(next, tokens);
+                }
+            } else if next.is_preprocessor_directive() {
+                return Err(PreProcessorError::ForbiddenDirective(next));
+            } else {
+                if keep {
+                    cache.push(next);
                 }
             }
+        }
 
-            if !terminated && else_token.is_none() {
+        if !terminated {
+            if let Some(else_token) = else_token {
+                return Err(PreProcessorError::UnterminatedElse(else_token));
+            } else {
                 return Err(PreProcessorError::UnterminatedIf(if_identifier));
             }
+        }
 
-            if else_token.is_some() {
-                while let Some(next) = tokens.next() {
-                    if next.is_end_if() {
-                        terminated = true;
-                        break;
-                    } else if next.is_directive() && !next.is_identifier() {
-                        return Err(PreProcessorError::ForbiddenDirective(next));
-                    } else {
-                        if !keep {
-                            if next.is_opcode() {
-                                self.opcode_count += 1;
-                            }
-
-                            cache.push(next);
-                        }
-                    }
-                }
+        return self.process_tokens(&mut cache.into_iter().peekable());
+    }
 
-                if !terminated {
-                    return Err(PreProcessorError::UnterminatedElse(else_token.unwrap()));
-                }
+    /// Reads the operand following a conditional directive and returns whether its block is kept.
+    ///
+    /// `if` and `elif` test an assembler flag, `ifdef` and `ifndef` test whether a constant or
+    /// label has been defined so far.
+    fn read_condition<T: Iterator<Item = Token>>(
+        &self,
+        identifier: &Token,
+        tokens: &mut T,
+    ) -> PreProcessorResult<bool> {
+        if let Some(flag) = tokens.next() {
+            if !flag.is_identifier() {
+                return Err(PreProcessorError::ExpectedIdentifierFlagFound(
+                    identifier.clone(),
+                    flag,
+                ));
             }
 
-            self.primary_output.append(&mut cache);
+            let name = flag.lexeme().string();
 
-            return Ok(());
+            return Ok(match identifier.token_type() {
+                TokenType::Ifdef => self.constants.contains_key(&name),
+                TokenType::Ifndef => !self.constants.contains_key(&name),
+                _ => self.flags.contains(&name),
+            });
         } else {
-            return Err(PreProcessorError::ExpectedIdentifierFoundEOF(if_identifier));
+            return Err(PreProcessorError::ExpectedIdentifierFoundEOF(
+                identifier.clone(),
+            ));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use alloc::rc::Rc;
     use alloc::string::ToString;
     use alloc::vec;
+    use either::Either;
     use vxl_iset::instruction_arguments::Register;
 
-    use crate::processing::Lexer;
+    use crate::processing::{Lexer, LexerBuilder, UnknownDirectivePolicy};
 
     use super::*;
 
@@ -402,19 +1045,104 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_constant_expression() {
+        assert_eq!(
+            process("%const A 6\n%const B A * 7\n%const C (1 + 2) * 4 - 10 / 3 << 1\n%const D A - 8\nldi $r0, A\nldi $r1, B\nldi $r2, C\nldi $r3, D"),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(6),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R1),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(42),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R2),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(18),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R3),
+                TokenType::Comma,
+                TokenType::SignedIntegerLiteral(-2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_expression_errors() {
+        let tokens =
+            Lexer::tokenize_string("%const B 2\n%const C 8 / (B - 2)".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+
+        match processor.primary_process() {
+            Err(PreProcessorError::DivisionByZero(range)) => {
+                assert_eq!(range.string(), "8 / (B - 2)");
+            }
+            other => panic!("Expected a division by zero, found {:?}", other),
+        }
+
+        let tokens = Lexer::tokenize_string("%const C 8 + D".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+
+        assert!(matches!(
+            processor.primary_process(),
+            Err(PreProcessorError::UndefinedConstant(_, _))
+        ));
+    }
+
     #[test]
     fn test_label() {
         assert_eq!(
             process("main:\n\tjmp func\nfunc:\n\tjmp main"),
             vec![
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::UnsignedIntegerLiteral(9),
                 TokenType::Opcode(0x37),
                 TokenType::UnsignedIntegerLiteral(0),
             ]
         );
     }
 
+    #[test]
+    fn test_forward_jump() {
+        assert_eq!(
+            process("jmp END\nhalt\nhalt\nEND:\nhalt"),
+            vec![
+                TokenType::Opcode(0x37),
+                TokenType::UnsignedIntegerLiteral(11),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let tokens = Lexer::tokenize_string("jmp NOWHERE".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+        processor.primary_process().unwrap();
+
+        assert!(matches!(
+            processor.secondary_process(),
+            Err(PreProcessorError::UndefinedLabel(_))
+        ));
+
+        let tokens = Lexer::tokenize_string("A:\nhalt\nA:\nhalt".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+
+        assert!(matches!(
+            processor.primary_process(),
+            Err(PreProcessorError::DuplicateLabel(_, _))
+        ));
+    }
+
     #[test]
     fn test_repeat() {
         assert_eq!(
@@ -442,11 +1170,11 @@ mod tests {
             process("%repeat 3\njmp FUNC\n%end_repeat\nFUNC:\nldi $r0, 42"),
             vec![
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::UnsignedIntegerLiteral(27),
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::UnsignedIntegerLiteral(27),
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(3),
+                TokenType::UnsignedIntegerLiteral(27),
                 TokenType::Opcode(0x3),
                 TokenType::Register(Register::R0),
                 TokenType::Comma,
@@ -455,6 +1183,117 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_repeat_halt() {
+        assert_eq!(
+            process("%repeat 3 halt %end_repeat"),
+            vec![
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeat_nested() {
+        assert_eq!(
+            process("%const N 1\n%repeat N + 1\n%repeat 2\nhalt\n%end_repeat\njmp END\n%end_repeat\n%repeat 0\nhalt\n%end_repeat\nEND:\nhalt"),
+            vec![
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x37),
+                TokenType::UnsignedIntegerLiteral(22),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x37),
+                TokenType::UnsignedIntegerLiteral(22),
+                TokenType::Opcode(0x45),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeat_negative_count() {
+        let tokens =
+            Lexer::tokenize_string("%repeat 1 - 2\nhalt\n%end_repeat".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+
+        assert!(matches!(
+            processor.primary_process(),
+            Err(PreProcessorError::ExpectedUnsignedIntegerFound(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_repeat_variable() {
+        assert_eq!(
+            process("%repeat i 3\nldi $r0, i\n%end_repeat"),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(0),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeat_constant_count_before_macro_call() {
+        assert_eq!(
+            process("%const N 2\n%macro z\nhalt\n%end_macro\n%repeat N\nz\n%end_repeat"),
+            vec![TokenType::Opcode(0x45), TokenType::Opcode(0x45)]
+        );
+    }
+
+    #[test]
+    fn test_repeat_nested_variables() {
+        let output =
+            process("%repeat i 2\n%repeat j 2\nldi $r0, i\nldi $r1, j\n%end_repeat\n%end_repeat");
+
+        let immediates: Vec<_> = output
+            .into_iter()
+            .filter_map(|t| match t {
+                TokenType::UnsignedIntegerLiteral(v) => Some(v),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(immediates, vec![0, 0, 0, 1, 1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_repeat_variable_shadows_constant() {
+        let tokens = Lexer::tokenize_string(
+            "%const i 9\n%repeat i 1\nldi $r0, i\n%end_repeat\nldi $r0, i".to_string(),
+        )
+        .unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+        processor.primary_process().unwrap();
+
+        assert!(matches!(
+            processor.warnings(),
+            [PreProcessorWarning::ShadowedConstant(_)]
+        ));
+
+        processor.secondary_process().unwrap();
+
+        assert_eq!(
+            processor.into_output()[3].token_type(),
+            TokenType::UnsignedIntegerLiteral(0)
+        );
+    }
+
     #[test]
     fn test_if_success() {
         assert_eq!(
@@ -516,7 +1355,7 @@ mod tests {
             ),
             vec![
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::UnsignedIntegerLiteral(9),
                 TokenType::Opcode(0x3),
                 TokenType::Register(Register::R0),
                 TokenType::Comma,
@@ -534,7 +1373,7 @@ mod tests {
             ),
             vec![
                 TokenType::Opcode(0x37),
-                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::UnsignedIntegerLiteral(9),
                 TokenType::Opcode(0x3),
                 TokenType::Register(Register::R0),
                 TokenType::Comma,
@@ -542,4 +1381,181 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_data_directives_in_blocks() {
+        assert_eq!(
+            process("%repeat 2\n%db 1\n%end_repeat"),
+            vec![
+                TokenType::DefineByte,
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::DefineByte,
+                TokenType::UnsignedIntegerLiteral(1),
+            ]
+        );
+
+        assert_eq!(
+            process("%ifndef A\n%ascii \"a\"\n%end_if"),
+            vec![TokenType::Ascii, TokenType::String("a".into())]
+        );
+    }
+
+    #[test]
+    fn test_macro() {
+        assert_eq!(
+            process("%macro load2 a b\nldi a, 0u1\nldi b, 0u2\n%end_macro\nload2 $r0, $r1\nload2 $r2, $r3"),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R1),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(2),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R2),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(1),
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R3),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_body_starting_with_label() {
+        assert_eq!(
+            process("%macro m\nlbl:\nhalt\n%end_macro\nm\njmp lbl"),
+            vec![
+                TokenType::Opcode(0x45),
+                TokenType::Opcode(0x37),
+                TokenType::UnsignedIntegerLiteral(0),
+            ]
+        );
+
+        assert_eq!(
+            process("%macro inner\nhalt\n%end_macro\n%macro outer\ninner\n%end_macro\nouter"),
+            vec![TokenType::Opcode(0x45)]
+        );
+    }
+
+    #[test]
+    fn test_string_constant() {
+        assert_eq!(
+            process("%const MSG \"hello\"\n%const GREETING MSG\n%ascii MSG\n%asciiz GREETING"),
+            vec![
+                TokenType::Ascii,
+                TokenType::String("hello".into()),
+                TokenType::Asciiz,
+                TokenType::String("hello".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_data_moves_labels() {
+        assert_eq!(
+            process("%ascii \"hi\\n\"\n%asciiz \"a\"\nL:\njmp L"),
+            vec![
+                TokenType::Ascii,
+                TokenType::String("hi\n".into()),
+                TokenType::Asciiz,
+                TokenType::String("a".into()),
+                TokenType::Opcode(0x37),
+                TokenType::UnsignedIntegerLiteral(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_constant_errors() {
+        let primary_process = |input: &str| {
+            let tokens = Lexer::tokenize_string(input.to_string()).unwrap();
+
+            return StringPreProcessor::new(tokens, HashSet::new()).primary_process();
+        };
+
+        assert!(matches!(
+            primary_process("%const MSG \"hello\"\nldi $r0, MSG"),
+            Err(PreProcessorError::StringConstantAsNumber(_))
+        ));
+
+        assert!(matches!(
+            primary_process("%const A 5\n%ascii A"),
+            Err(PreProcessorError::NumericConstantAsString(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_raw_directive_skipped() {
+        let mut lexer = LexerBuilder::new()
+            .unknown_directive_policy(UnknownDirectivePolicy::Ignore)
+            .build(Either::Right(Rc::new(
+                "%mytool_hint 3, foo\nhalt".to_string().into(),
+            )));
+
+        lexer.process().unwrap();
+
+        let mut processor = StringPreProcessor::new(lexer.into_tokens(), HashSet::new());
+
+        processor.primary_process().unwrap();
+        processor.secondary_process().unwrap();
+
+        assert_eq!(
+            processor
+                .into_output()
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>(),
+            vec![TokenType::Opcode(0x45)]
+        );
+    }
+
+    #[test]
+    fn test_ascii_missing_string() {
+        let tokens = Lexer::tokenize_string("%ascii 42".to_string()).unwrap();
+
+        let mut processor = StringPreProcessor::new(tokens, HashSet::new());
+
+        assert!(matches!(
+            processor.primary_process(),
+            Err(PreProcessorError::ExpectedStringFound(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_if_elif_else() {
+        let input = "%if A\nldi $r0, 1\n%elif B\nldi $r0, 2\n%else\nldi $r0, 3\n%end_if";
+
+        assert_eq!(
+            process_flags(input, &["A", "B"]),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(1),
+            ]
+        );
+        assert_eq!(
+            process_flags(input, &["B"]),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(2),
+            ]
+        );
+        assert_eq!(
+            process_flags(input, &[]),
+            vec![
+                TokenType::Opcode(0x3),
+                TokenType::Register(Register::R0),
+                TokenType::Comma,
+                TokenType::UnsignedIntegerLiteral(3),
+            ]
+        );
+    }
 }
@@ -0,0 +1,90 @@
+use alloc::collections::VecDeque;
+use core::iter::Fuse;
+
+/// Wraps a token iterator to allow looking any number of items ahead.
+///
+/// Items are pulled from the underlying iterator only as far as they are peeked and are kept until
+/// consumed by `next`, so nothing is lexed twice. Once the iterator is exhausted every peek returns
+/// `None`.
+#[derive(Clone, Debug)]
+pub struct TokenBuffer<I: Iterator> {
+    inner: Fuse<I>,
+    buffer: VecDeque<I::Item>,
+}
+
+impl<I: Iterator> TokenBuffer<I> {
+    pub fn new(inner: I) -> Self {
+        return Self {
+            inner: inner.fuse(),
+            buffer: VecDeque::new(),
+        };
+    }
+
+    /// The next item, without consuming it.
+    pub fn peek(&mut self) -> Option<&I::Item> {
+        return self.peek_nth(0);
+    }
+
+    /// The item `n` places ahead of the next one, without consuming anything.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&I::Item> {
+        while self.buffer.len() <= n {
+            self.buffer.push_back(self.inner.next()?);
+        }
+
+        return self.buffer.get(n);
+    }
+}
+
+impl<I: Iterator> Iterator for TokenBuffer<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.buffer.pop_front() {
+            return Some(item);
+        }
+
+        return self.inner.next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+
+    use crate::processing::Lexer;
+    use crate::text_mapping::FileInfoManager;
+    use crate::token::TokenType;
+
+    use super::*;
+
+    #[test]
+    fn test_peek_nth() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "X :".to_string());
+
+        let mut buffer = TokenBuffer::new(Lexer::stream(f));
+
+        assert_eq!(
+            buffer.peek_nth(1).unwrap().as_ref().unwrap().token_type(),
+            TokenType::Colon
+        );
+        assert_eq!(buffer.peek_nth(2), None);
+
+        assert_eq!(
+            buffer.next().unwrap().unwrap().token_type(),
+            TokenType::Identifier
+        );
+        assert_eq!(
+            buffer.peek().unwrap().as_ref().unwrap().token_type(),
+            TokenType::Colon
+        );
+        assert_eq!(
+            buffer.next().unwrap().unwrap().token_type(),
+            TokenType::Colon
+        );
+
+        assert_eq!(buffer.peek(), None);
+        assert_eq!(buffer.peek_nth(3), None);
+        assert_eq!(buffer.next(), None);
+    }
+}
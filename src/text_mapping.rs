@@ -1,10 +1,18 @@
 use alloc::rc::Rc;
 use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
+use core::ops::Range;
 use either::Either;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+use crate::error::LexerError;
+use crate::processing::Lexer;
+use crate::token::Token;
+
+/// A location in a source, positions are ordered by their index into the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     index: usize,
     row: usize,
@@ -12,20 +20,28 @@ pub struct Position {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextRange {
     starting_pos: Position,
     ending_pos: Position,
+    #[cfg_attr(feature = "with-serde", serde(with = "source_ref"))]
     source: Either<FilePtr, Rc<AssemblyString>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssemblyString(String);
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileInfo {
     file_name: String,
     file_contents: String,
     id: usize,
+    /// The byte offset at which each line of the contents starts.
+    line_starts: Vec<usize>,
+    /// The character offset at which each line of the contents starts.
+    line_start_chars: Vec<usize>,
 }
 
 pub type Source = Either<FilePtr, Rc<AssemblyString>>;
@@ -52,11 +68,18 @@ impl Position {
     pub fn col(&self) -> usize {
         return self.col;
     }
+
+    /// Returns the row and column counting from 1, as editors number lines and columns.
+    pub fn display_line_col(&self) -> (usize, usize) {
+        return (self.row + 1, self.col + 1);
+    }
 }
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return write!(f, "{}:{}", self.row, self.col);
+        let (line, col) = self.display_line_col();
+
+        return write!(f, "{}:{}", line, col);
     }
 }
 
@@ -77,10 +100,56 @@ impl TextRange {
         return self.ending_pos;
     }
 
+    pub fn source(&self) -> &Source {
+        return &self.source;
+    }
+
     pub fn len(&self) -> usize {
         return self.ending_pos.index() - self.starting_pos.index();
     }
 
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// The number of characters covered by this range.
+    pub fn char_len(&self) -> usize {
+        return self.len();
+    }
+
+    /// The number of bytes covered by this range in its source text.
+    pub fn byte_len(&self) -> usize {
+        return self.as_byte_range().len();
+    }
+
+    /// The character offsets of this range in its source text.
+    pub fn as_char_range(&self) -> Range<usize> {
+        return self.starting_pos.index()..self.ending_pos.index();
+    }
+
+    /// The byte offsets of this range in its source text, which can slice the text directly.
+    pub fn as_byte_range(&self) -> Range<usize> {
+        let offset = |index: usize| match &self.source {
+            Either::Left(f) => f.byte_offset(index).unwrap_or(f.contents().len()),
+            Either::Right(s) => byte_offset(s.as_str(), index),
+        };
+
+        return offset(self.starting_pos.index())..offset(self.ending_pos.index());
+    }
+
+    /// Returns the smallest range covering both ranges, or None if they come from different sources.
+    pub fn merge(&self, other: &TextRange) -> Option<TextRange> {
+        if self.source != other.source {
+            return None;
+        }
+
+        return Some(TextRange::new(
+            self.starting_pos.min(other.starting_pos),
+            self.ending_pos.max(other.ending_pos),
+            self.source.clone(),
+        ));
+    }
+
     pub fn string(&self) -> String {
         return match &self.source {
             Either::Left(f) => f.substring(self.starting_pos, self.ending_pos),
@@ -88,6 +157,11 @@ impl TextRange {
         };
     }
 
+    /// Returns whether the position lies within this range, inclusive of start but exclusive of end.
+    pub fn contains(&self, pos: &Position) -> bool {
+        return self.starting_pos <= *pos && *pos < self.ending_pos;
+    }
+
     pub fn compare_contents(&self, other: &Self) -> bool {
         if self.len() != other.len() {
             return false;
@@ -128,15 +202,21 @@ impl fmt::Display for TextRange {
 }
 
 impl AssemblyString {
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+
     /// Returns a clone of the sub-string from start to end, inclusive of start but exclusive of end.
     ///
-    /// Panics if the end position is greater than the lenght of the file.
+    /// Positions count characters, so the sub-string may hold multi-byte characters. Panics if the
+    /// end position is greater than the length of the string.
     pub fn substring(&self, start: Position, end: Position) -> String {
-        if end.index() > self.0.len() {
+        if end.index() > self.0.chars().count() {
             panic!("End position larger than string contents.");
         }
 
-        return self.0[start.index()..end.index()].to_string();
+        return self.0[byte_offset(&self.0, start.index())..byte_offset(&self.0, end.index())]
+            .to_string();
     }
 }
 
@@ -164,10 +244,24 @@ impl Into<String> for AssemblyString {
 impl FileInfo {
     /// Creates a new instance of FileInfo.
     fn new(id: usize, name: String, contents: String) -> Self {
+        let mut line_starts = vec![0];
+        let mut line_start_chars = vec![0];
+        let bytes = contents.as_bytes();
+
+        // Line breaks are counted the same way as the lexer, CRLF being a single break.
+        for (chars, (i, c)) in contents.char_indices().enumerate() {
+            if c == '\n' || (c == '\r' && bytes.get(i + 1) != Some(&b'\n')) {
+                line_starts.push(i + 1);
+                line_start_chars.push(chars + 1);
+            }
+        }
+
         return Self {
             id,
             file_name: name,
             file_contents: contents,
+            line_starts,
+            line_start_chars,
         };
     }
 
@@ -183,15 +277,80 @@ impl FileInfo {
         return &self.file_contents;
     }
 
+    /// Returns the text of the given zero-based row, without its line break.
+    pub fn line_text(&self, row: usize) -> Option<&str> {
+        let start = *self.line_starts.get(row)?;
+        let end = match self.line_starts.get(row + 1) {
+            Some(next) => *next,
+            None => self.file_contents.len(),
+        };
+
+        return Some(self.file_contents[start..end].trim_end_matches(&['\n', '\r'][..]));
+    }
+
+    /// The position of the first character of the given zero-based row.
+    pub fn line_start(&self, row: usize) -> Option<Position> {
+        return self
+            .line_starts
+            .get(row)
+            .map(|start| self.offset_to_position(*start));
+    }
+
+    /// Converts a byte offset into the contents to its position.
+    ///
+    /// The index and column of the position count characters, as the lexer's do, with a tab
+    /// counting as a single column. Panics if the offset is not on a character boundary.
+    pub fn offset_to_position(&self, idx: usize) -> Position {
+        let row = match self.line_starts.binary_search(&idx) {
+            Ok(row) => row,
+            Err(next) => next - 1,
+        };
+
+        let index = self.file_contents[..idx].chars().count();
+        let col = self.file_contents[self.line_starts[row]..idx]
+            .chars()
+            .count();
+
+        return Position::new(index, row, col);
+    }
+
+    /// Converts a character index into the contents to its byte offset, or None if it lies past
+    /// the end.
+    ///
+    /// Only the line holding the character is scanned, so this stays cheap on large files.
+    pub fn byte_offset(&self, char_index: usize) -> Option<usize> {
+        let row = match self.line_start_chars.binary_search(&char_index) {
+            Ok(row) => row,
+            Err(next) => next - 1,
+        };
+
+        let line_start = self.line_starts[row];
+        let line = &self.file_contents[line_start..];
+
+        return match line
+            .char_indices()
+            .nth(char_index - self.line_start_chars[row])
+        {
+            Some((offset, _)) => Some(line_start + offset),
+            None if line.chars().count() == char_index - self.line_start_chars[row] => {
+                Some(self.file_contents.len())
+            }
+            None => None,
+        };
+    }
+
     /// Returns a clone of the sub-string from start to end, inclusive of start but exclusive of end.
     ///
-    /// Panics if the end position is greater than the lenght of the file.
+    /// Positions count characters, so the sub-string may hold multi-byte characters. Panics if the
+    /// end position is greater than the length of the file.
     pub fn substring(&self, start: Position, end: Position) -> String {
-        if end.index() > self.file_contents.len() {
-            panic!("End position larger than file contents.");
-        }
+        let end = match self.byte_offset(end.index()) {
+            Some(end) => end,
+            None => panic!("End position larger than file contents."),
+        };
+        let start = self.byte_offset(start.index()).unwrap_or(end);
 
-        return self.file_contents[start.index()..end.index()].to_string();
+        return self.file_contents[start..end].to_string();
     }
 }
 
@@ -216,6 +375,21 @@ impl FileInfoManager {
         return file_info;
     }
 
+    /// Registers a new file and lexes it, returning the file alongside its tokens.
+    ///
+    /// The file stays registered when lexing fails, so that the error can still be reported
+    /// against it.
+    pub fn tokenize_source(
+        &mut self,
+        name: String,
+        contents: String,
+    ) -> Result<(Rc<FileInfo>, Vec<Token>), LexerError> {
+        let file = self.new_file(name, contents);
+        let tokens = Lexer::tokenize(file.clone())?;
+
+        return Ok((file, tokens));
+    }
+
     pub fn get_file_info(&self, name: &str) -> Option<Rc<FileInfo>> {
         for f in &self.file_info_refs {
             if f.name() == name {
@@ -229,4 +403,372 @@ impl FileInfoManager {
     pub fn get_file_info_refs(&self) -> &Vec<Rc<FileInfo>> {
         return &self.file_info_refs;
     }
+
+    /// Points deserialized tokens back at the files held by this manager.
+    ///
+    /// Tokens only serialize the id and name of their file, so they deserialize with an empty
+    /// copy of it. Returns None if a token names a file this manager does not hold.
+    pub fn attach_tokens(&self, tokens: Vec<Token>) -> Option<Vec<Token>> {
+        let mut attached = Vec::with_capacity(tokens.len());
+
+        for token in tokens {
+            let range = token.lexeme();
+
+            let source = match range.source() {
+                Either::Left(file) => {
+                    let held = self.file_info_refs.get(file.id())?;
+
+                    if held.name() != file.name() {
+                        return None;
+                    }
+
+                    Either::Left(held.clone())
+                }
+                Either::Right(string) => Either::Right(string.clone()),
+            };
+
+            attached.push(Token::new(
+                token.token_type(),
+                TextRange::new(range.start(), range.end(), source),
+            ));
+        }
+
+        return Some(attached);
+    }
+}
+
+/// Serializes the file of a range by its id and name rather than its contents, so that a token
+/// stream does not repeat the file for every token. `FileInfoManager::attach_tokens` restores the
+/// files once deserialized.
+#[cfg(feature = "with-serde")]
+mod source_ref {
+    use alloc::rc::Rc;
+    use alloc::string::String;
+    use either::Either;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{AssemblyString, FileInfo, Source};
+
+    #[derive(Serialize)]
+    enum SourceRef<'a> {
+        File { id: usize, name: &'a str },
+        String(&'a AssemblyString),
+    }
+
+    #[derive(Deserialize)]
+    enum OwnedSourceRef {
+        File { id: usize, name: String },
+        String(AssemblyString),
+    }
+
+    pub fn serialize<S: Serializer>(source: &Source, serializer: S) -> Result<S::Ok, S::Error> {
+        let source = match source {
+            Either::Left(file) => SourceRef::File {
+                id: file.id(),
+                name: file.name().as_str(),
+            },
+            Either::Right(string) => SourceRef::String(string),
+        };
+
+        return source.serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Source, D::Error> {
+        return Ok(match OwnedSourceRef::deserialize(deserializer)? {
+            OwnedSourceRef::File { id, name } => {
+                Either::Left(Rc::new(FileInfo::new(id, name, String::new())))
+            }
+            OwnedSourceRef::String(string) => Either::Right(Rc::new(string)),
+        });
+    }
+}
+
+/// Converts a character index into the text to a byte offset, clamped to the end of the text.
+fn byte_offset(text: &str, char_index: usize) -> usize {
+    return match text.char_indices().nth(char_index) {
+        Some((offset, _)) => offset,
+        None => text.len(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processing::Lexer;
+    use crate::token::TokenType;
+
+    use super::*;
+
+    #[test]
+    fn test_tokenize_source() {
+        let mut f_man = FileInfoManager::new();
+
+        let (file, tokens) = f_man
+            .tokenize_source("main.vsm".to_string(), "halt".to_string())
+            .unwrap();
+
+        assert_eq!(f_man.get_file_info("main.vsm"), Some(file.clone()));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type(), TokenType::Opcode(0x45));
+        assert_eq!(tokens[0].lexeme().source(), &Either::Left(file));
+
+        assert!(f_man
+            .tokenize_source("bad.vsm".to_string(), "halt ?".to_string())
+            .is_err());
+        assert!(f_man.get_file_info("bad.vsm").is_some());
+    }
+
+    #[test]
+    fn test_attach_tokens() {
+        let mut f_man = FileInfoManager::new();
+        f_man.new_file("lib.vsm".to_string(), "halt".to_string());
+
+        let (file, tokens) = f_man
+            .tokenize_source("main.vsm".to_string(), "ldi $r0, 5".to_string())
+            .unwrap();
+
+        // As the tokens deserialize, with an empty copy of their file.
+        let detached = Rc::new(FileInfo::new(file.id(), file.name().clone(), String::new()));
+        let detached_tokens: Vec<Token> = tokens
+            .iter()
+            .map(|t| {
+                Token::new(
+                    t.token_type(),
+                    TextRange::new(
+                        t.lexeme().start(),
+                        t.lexeme().end(),
+                        Either::Left(detached.clone()),
+                    ),
+                )
+            })
+            .collect();
+
+        let attached = f_man.attach_tokens(detached_tokens.clone()).unwrap();
+
+        assert_eq!(attached, tokens);
+        assert!(attached
+            .iter()
+            .all(|t| Rc::ptr_eq(t.file().unwrap(), &file)));
+
+        let renamed = Rc::new(FileInfo::new(
+            file.id(),
+            "other.vsm".to_string(),
+            String::new(),
+        ));
+        let missing = Rc::new(FileInfo::new(5, file.name().clone(), String::new()));
+
+        for source in [renamed, missing] {
+            let token = Token::new(
+                TokenType::Comma,
+                TextRange::new(
+                    Position::new(0, 0, 0),
+                    Position::new(0, 0, 0),
+                    Either::Left(source),
+                ),
+            );
+
+            assert_eq!(f_man.attach_tokens(vec![token]), None);
+        }
+    }
+
+    #[test]
+    fn test_byte_and_char_ranges() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "ldi $r0, 5".to_string());
+
+        let ascii = TextRange::new(
+            Position::new(5, 0, 5),
+            Position::new(7, 0, 7),
+            Either::Left(f.clone()),
+        );
+
+        assert_eq!(ascii.as_char_range(), 5..7);
+        assert_eq!(ascii.as_byte_range(), 5..7);
+        assert_eq!(&f.contents()[ascii.as_byte_range()], "r0");
+
+        let g = f_man.new_file(
+            String::new(),
+            "%ascii \"h\u{e9}llo \u{1F600}\"\nhalt".to_string(),
+        );
+
+        let string = TextRange::new(
+            Position::new(8, 0, 8),
+            Position::new(15, 0, 15),
+            Either::Left(g.clone()),
+        );
+        let halt = TextRange::new(
+            Position::new(17, 1, 0),
+            Position::new(21, 1, 4),
+            Either::Left(g.clone()),
+        );
+
+        assert_eq!(string.as_char_range(), 8..15);
+        assert_eq!(string.as_byte_range(), 8..19);
+        assert_eq!(
+            &g.contents()[string.as_byte_range()],
+            "h\u{e9}llo \u{1F600}"
+        );
+
+        assert_eq!(halt.as_char_range(), 17..21);
+        assert_eq!(halt.as_byte_range(), 21..25);
+        assert_eq!(&g.contents()[halt.as_byte_range()], "halt");
+    }
+
+    #[test]
+    fn test_string_after_multi_byte_text() {
+        let input = "# caf\u{e9}\nhalt \"\u{e9}\" 0x1f\n%ascii \"h\u{e9}llo \u{1F600}\"\nhalt";
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), input.to_string());
+        let tokens = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| t.lexeme().string())
+                .collect::<Vec<_>>(),
+            alloc::vec![
+                "halt",
+                "\u{e9}",
+                "1f",
+                "ascii",
+                "h\u{e9}llo \u{1F600}",
+                "halt"
+            ]
+        );
+        assert_eq!(
+            tokens[2].token_type(),
+            TokenType::UnsignedIntegerLiteral(0x1f)
+        );
+        assert_eq!(tokens[0].token_type(), TokenType::Opcode(0x45));
+        assert_eq!(tokens[5].token_type(), TokenType::Opcode(0x45));
+
+        let tokens = Lexer::tokenize_string(input.to_string()).unwrap();
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| t.lexeme().string())
+                .collect::<Vec<_>>(),
+            alloc::vec![
+                "halt",
+                "\u{e9}",
+                "1f",
+                "ascii",
+                "h\u{e9}llo \u{1F600}",
+                "halt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_position_ordering() {
+        let first = Position::new(0, 0, 0);
+        let second = Position::new(4, 0, 4);
+        let third = Position::new(6, 1, 0);
+
+        assert!(first < second);
+        assert!(second < third);
+        assert_eq!(first.max(third), third);
+
+        let mut positions = alloc::vec![third, first, second];
+        positions.sort();
+
+        assert_eq!(positions, alloc::vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_range_merge() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "ldi $r0, 5".to_string());
+        let g = f_man.new_file(String::new(), "ldi $r0, 5".to_string());
+
+        let range = |start: usize, end: usize, file: &FilePtr| {
+            return TextRange::new(
+                Position::new(start, 0, start),
+                Position::new(end, 0, end),
+                Either::Left(file.clone()),
+            );
+        };
+
+        // Adjacent ranges.
+        assert_eq!(
+            range(0, 3, &f).merge(&range(3, 4, &f)),
+            Some(range(0, 4, &f))
+        );
+        // Disjoint ranges, in either order.
+        assert_eq!(
+            range(9, 10, &f).merge(&range(0, 3, &f)),
+            Some(range(0, 10, &f))
+        );
+        assert_eq!(range(0, 3, &f).merge(&range(0, 3, &g)), None);
+
+        assert!(range(4, 4, &f).is_empty());
+        assert!(!range(4, 5, &f).is_empty());
+    }
+
+    #[test]
+    fn test_line_text() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "halt\r\nldi $r0, 5\nret".to_string());
+
+        assert_eq!(f.line_text(0), Some("halt"));
+        assert_eq!(f.line_text(1), Some("ldi $r0, 5"));
+        assert_eq!(f.line_text(2), Some("ret"));
+        assert_eq!(f.line_text(3), None);
+
+        assert_eq!(f.offset_to_position(0), Position::new(0, 0, 0));
+        assert_eq!(f.offset_to_position(10), Position::new(10, 1, 4));
+        assert_eq!(f.offset_to_position(17), Position::new(17, 2, 0));
+
+        let f = f_man.new_file(String::new(), "é\nhalté\nret".to_string());
+
+        assert_eq!(f.offset_to_position(2), Position::new(1, 0, 1));
+        assert_eq!(f.offset_to_position(3), Position::new(2, 1, 0));
+        assert_eq!(f.offset_to_position(9), Position::new(7, 1, 5));
+        assert_eq!(f.offset_to_position(10), Position::new(8, 2, 0));
+        assert_eq!(f.line_start(1), Some(Position::new(2, 1, 0)));
+        assert_eq!(f.line_start(3), None);
+    }
+
+    #[test]
+    fn test_range_contains() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "ldi $r0, 5".to_string());
+
+        let range = TextRange::new(
+            Position::new(4, 0, 4),
+            Position::new(7, 0, 7),
+            Either::Left(f),
+        );
+
+        assert!(range.contains(&Position::new(4, 0, 4)));
+        assert!(range.contains(&Position::new(6, 0, 6)));
+        assert!(!range.contains(&Position::new(7, 0, 7)));
+        assert!(!range.contains(&Position::new(3, 0, 3)));
+    }
+
+    #[test]
+    fn test_range_lengths() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "ldi $r0, 5".to_string());
+
+        let ascii = TextRange::new(
+            Position::new(4, 0, 4),
+            Position::new(7, 0, 7),
+            Either::Left(f),
+        );
+
+        assert_eq!(ascii.char_len(), ascii.string().chars().count());
+        assert_eq!(ascii.byte_len(), 3);
+
+        let g = f_man.new_file(String::new(), "%ascii \"héllo\"".to_string());
+
+        let multi_byte = TextRange::new(
+            Position::new(8, 0, 8),
+            Position::new(13, 0, 13),
+            Either::Left(g),
+        );
+
+        assert_eq!(multi_byte.char_len(), "héllo".chars().count());
+        assert_eq!(multi_byte.byte_len(), "héllo".len());
+    }
 }
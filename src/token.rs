@@ -1,35 +1,217 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
 use vxl_iset::instruction_arguments::Register;
 
-use crate::text_mapping::TextRange;
+use crate::disasm::register_name;
+use crate::text_mapping::{FilePtr, Position, TextRange};
 use paste::paste;
 
 /// Represents an understandable token for the preprocessor and parser
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     tp: TokenType,
     lexeme: TextRange,
+    /// The bit width annotating an integer literal, as in `0u8:255`.
+    #[cfg_attr(
+        feature = "with-serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    width: Option<u8>,
 }
 
 /// The supported token types
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
-    Register(Register),
+    Register(#[cfg_attr(feature = "with-serde", serde(with = "register_code"))] Register),
+    /// An inclusive range of general purpose registers, such as `$r0-$r3`.
+    RegisterRange(
+        #[cfg_attr(feature = "with-serde", serde(with = "register_code"))] Register,
+        #[cfg_attr(feature = "with-serde", serde(with = "register_code"))] Register,
+    ),
     UnsignedIntegerLiteral(u64),
     SignedIntegerLiteral(i64),
     FloatLiteral(f64),
     Opcode(u8),
-    String,
+    /// A string literal, holding its contents with escape sequences resolved.
+    String(Rc<str>),
     Comma,
     Colon,
+    /// A binary operator in a constant expression.
+    Operator(Operator),
+    LParen,
+    RParen,
+    /// The text of a comment, only produced when the lexer is asked to keep comments.
+    Comment,
+    /// The end of a line, only produced when the lexer is asked to emit newlines.
+    Newline,
+    /// The end of the input, only produced when the lexer is asked to emit it.
+    Eof,
 
     Identifier,
+    /// A label beginning with '.', scoped to the most recent label without one.
+    LocalLabel,
     Constant,
     Import,
     If,
+    Ifdef,
+    Ifndef,
+    Elif,
     Else,
     Endif,
     Repeat,
     EndRepeat,
+    Macro,
+    EndMacro,
+    Align,
+    DefineByte,
+    DefineWord,
+    DefineDword,
+    DefineQword,
+    Ascii,
+    Asciiz,
+    Fill,
+    Org,
+    Section,
+    ErrorDirective,
+    Entry,
+    Global,
+    Extern,
+    /// A directive the lexer does not know, only produced when unknown directives are ignored.
+    RawDirective,
+}
+
+/// Float literals are compared by their bit pattern, so that equality is reflexive and tokens can
+/// be compared exactly in tests. A NaN literal equals a NaN with the same bits, and `0.0` differs
+/// from `-0.0`, just as the two encode differently.
+impl PartialEq for TokenType {
+    fn eq(&self, other: &Self) -> bool {
+        // No wildcard, so a variant added without deciding how it compares fails to compile here.
+        return match (self, other) {
+            (TokenType::FloatLiteral(a), TokenType::FloatLiteral(b)) => a.to_bits() == b.to_bits(),
+            (TokenType::Register(a), TokenType::Register(b)) => a == b,
+            (TokenType::RegisterRange(a1, a2), TokenType::RegisterRange(b1, b2)) => {
+                a1 == b1 && a2 == b2
+            }
+            (TokenType::UnsignedIntegerLiteral(a), TokenType::UnsignedIntegerLiteral(b)) => a == b,
+            (TokenType::SignedIntegerLiteral(a), TokenType::SignedIntegerLiteral(b)) => a == b,
+            (TokenType::Opcode(a), TokenType::Opcode(b)) => a == b,
+            (TokenType::String(a), TokenType::String(b)) => a == b,
+            (TokenType::Operator(a), TokenType::Operator(b)) => a == b,
+            (
+                TokenType::FloatLiteral(_)
+                | TokenType::Register(_)
+                | TokenType::RegisterRange(_, _)
+                | TokenType::UnsignedIntegerLiteral(_)
+                | TokenType::SignedIntegerLiteral(_)
+                | TokenType::Opcode(_)
+                | TokenType::String(_)
+                | TokenType::Operator(_),
+                _,
+            ) => false,
+            (
+                TokenType::Comma
+                | TokenType::Colon
+                | TokenType::LParen
+                | TokenType::RParen
+                | TokenType::Comment
+                | TokenType::Newline
+                | TokenType::Eof
+                | TokenType::Identifier
+                | TokenType::LocalLabel
+                | TokenType::Constant
+                | TokenType::Import
+                | TokenType::If
+                | TokenType::Ifdef
+                | TokenType::Ifndef
+                | TokenType::Elif
+                | TokenType::Else
+                | TokenType::Endif
+                | TokenType::Repeat
+                | TokenType::EndRepeat
+                | TokenType::Macro
+                | TokenType::EndMacro
+                | TokenType::Align
+                | TokenType::DefineByte
+                | TokenType::DefineWord
+                | TokenType::DefineDword
+                | TokenType::DefineQword
+                | TokenType::Ascii
+                | TokenType::Asciiz
+                | TokenType::Fill
+                | TokenType::Org
+                | TokenType::Section
+                | TokenType::ErrorDirective
+                | TokenType::Entry
+                | TokenType::Global
+                | TokenType::Extern
+                | TokenType::RawDirective,
+                _,
+            ) => core::mem::discriminant(self) == core::mem::discriminant(other),
+        };
+    }
+}
+
+impl Eq for TokenType {}
+
+/// The binary operators accepted in constant expressions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+    Xor,
+}
+
+impl Operator {
+    /// The binding strength of this operator, following C. Higher binds tighter.
+    pub const fn precedence(&self) -> u8 {
+        return match self {
+            Operator::Multiply | Operator::Divide | Operator::Remainder => 5,
+            Operator::Add | Operator::Subtract => 4,
+            Operator::ShiftLeft | Operator::ShiftRight => 3,
+            Operator::And => 2,
+            Operator::Xor => 1,
+            Operator::Or => 0,
+        };
+    }
+
+    /// Applies this operator to two integers. Returns `None` on overflow, division by zero or a
+    /// shift of 64 bits or more.
+    pub fn apply(&self, lhs: i128, rhs: i128) -> Option<i128> {
+        return match self {
+            Operator::Add => lhs.checked_add(rhs),
+            Operator::Subtract => lhs.checked_sub(rhs),
+            Operator::Multiply => lhs.checked_mul(rhs),
+            Operator::Divide => lhs.checked_div(rhs),
+            Operator::Remainder => lhs.checked_rem(rhs),
+            Operator::ShiftLeft | Operator::ShiftRight => match u32::try_from(rhs) {
+                Ok(shift) if shift < 64 => {
+                    if *self == Operator::ShiftLeft {
+                        lhs.checked_shl(shift)
+                    } else {
+                        lhs.checked_shr(shift)
+                    }
+                }
+                _ => None,
+            },
+            Operator::And => Some(lhs & rhs),
+            Operator::Or => Some(lhs | rhs),
+            Operator::Xor => Some(lhs ^ rhs),
+        };
+    }
 }
 
 macro_rules! match_variant {
@@ -63,12 +245,23 @@ macro_rules! match_variant {
 impl Token {
     /// Creates a new token
     pub fn new(tp: TokenType, lexeme: TextRange) -> Self {
-        return Self { tp, lexeme };
+        return Self {
+            tp,
+            lexeme,
+            width: None,
+        };
+    }
+
+    /// Sets the bit width annotating this integer literal.
+    pub fn with_width(mut self, width: u8) -> Self {
+        self.width = Some(width);
+
+        return self;
     }
 
     /// Returns the token type of this token.
     pub fn token_type(&self) -> TokenType {
-        return self.tp;
+        return self.tp.clone();
     }
 
     /// The raw lexeme for this token.
@@ -76,54 +269,607 @@ impl Token {
         return &self.lexeme;
     }
 
+    /// The bit width given to this integer literal by an annotation such as `0u8:255`, or None
+    /// if it has none.
+    pub fn width(&self) -> Option<u8> {
+        return self.width;
+    }
+
+    /// The range of source text this token covers, the same as `lexeme`.
+    pub fn range(&self) -> &TextRange {
+        return &self.lexeme;
+    }
+
+    /// The length of this token's lexeme in characters.
+    pub fn len(&self) -> usize {
+        return self.lexeme.char_len();
+    }
+
+    /// Whether this token's lexeme covers no characters.
+    pub fn is_empty(&self) -> bool {
+        return self.lexeme.is_empty();
+    }
+
+    /// The source text this token was lexed from.
+    pub fn source_text(&self) -> String {
+        return self.lexeme.string();
+    }
+
+    /// The file this token was lexed from, or None if it was lexed from a string.
+    pub fn file(&self) -> Option<&FilePtr> {
+        return self.lexeme.source().as_ref().left();
+    }
+
+    /// The name of the file this token was lexed from, or None if it was lexed from a string.
+    pub fn file_name(&self) -> Option<&str> {
+        return self.file().map(|f| f.name().as_str());
+    }
+
     match_variant!(args
         TokenType::Register: register,
+        TokenType::RegisterRange: register_range,
         TokenType::UnsignedIntegerLiteral: unsigned_integer,
         TokenType::SignedIntegerLiteral: signed_integer,
         TokenType::FloatLiteral: float,
-        TokenType::Opcode: opcode
+        TokenType::Opcode: opcode,
+        TokenType::String: string,
+        TokenType::Operator: operator
     );
 
     match_variant!(
-        TokenType::String: string,
         TokenType::Comma: comma,
         TokenType::Colon: colon,
+        TokenType::LParen: l_paren,
+        TokenType::RParen: r_paren,
+        TokenType::Comment: comment,
+        TokenType::Newline: newline,
+        TokenType::Eof: eof,
         TokenType::Identifier: identifier,
+        TokenType::LocalLabel: local_label,
         TokenType::Constant: constant,
         TokenType::Import: import,
         TokenType::If: r#if,
+        TokenType::Ifdef: ifdef,
+        TokenType::Ifndef: ifndef,
+        TokenType::Elif: elif,
         TokenType::Else: r#else,
         TokenType::Endif: end_if,
         TokenType::Repeat: repeat,
-        TokenType::EndRepeat: end_repeat
+        TokenType::EndRepeat: end_repeat,
+        TokenType::Macro: r#macro,
+        TokenType::EndMacro: end_macro,
+        TokenType::Align: align,
+        TokenType::DefineByte: define_byte,
+        TokenType::DefineWord: define_word,
+        TokenType::DefineDword: define_dword,
+        TokenType::DefineQword: define_qword,
+        TokenType::Ascii: ascii,
+        TokenType::Asciiz: asciiz,
+        TokenType::Fill: fill,
+        TokenType::Org: org,
+        TokenType::Section: section,
+        TokenType::ErrorDirective: error_directive,
+        TokenType::Entry: entry,
+        TokenType::Global: global,
+        TokenType::Extern: r#extern,
+        TokenType::RawDirective: raw_directive
     );
 
     pub fn is_directive(&self) -> bool {
-        return match self.tp {
-            TokenType::Identifier
-            | TokenType::Constant
+        return self.tp.is_directive();
+    }
+
+    pub fn is_preprocessor_directive(&self) -> bool {
+        return self.tp.is_preprocessor_directive();
+    }
+
+    pub fn is_data_directive(&self) -> bool {
+        return self.tp.is_data_directive();
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        return self.tp.is_trivia();
+    }
+}
+
+/// The spellings of every directive recognised by `TokenType::match_identifier`.
+pub(crate) const DIRECTIVE_NAMES: &[&str] = &[
+    "import",
+    "const",
+    "if",
+    "ifdef",
+    "ifndef",
+    "elif",
+    "else",
+    "end_if",
+    "repeat",
+    "end_repeat",
+    "macro",
+    "end_macro",
+    "align",
+    "db",
+    "dw",
+    "dd",
+    "dq",
+    "ascii",
+    "asciiz",
+    "fill",
+    "org",
+    "section",
+    "error",
+    "entry",
+    "global",
+    "extern",
+];
+
+/// The largest edit distance at which a known directive is suggested for an unknown one.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+impl TokenType {
+    /// Whether this is an integer or float literal.
+    pub const fn is_numeric_literal(&self) -> bool {
+        return match self {
+            TokenType::UnsignedIntegerLiteral(_)
+            | TokenType::SignedIntegerLiteral(_)
+            | TokenType::FloatLiteral(_) => true,
+            _ => false,
+        };
+    }
+
+    /// Whether this is an assembler directive. Identifiers are included as they name labels and
+    /// constants.
+    pub const fn is_directive(&self) -> bool {
+        return match self {
+            TokenType::Identifier => true,
+            _ => self.is_preprocessor_directive() || self.is_data_directive(),
+        };
+    }
+
+    /// Whether this is a directive the preprocessor carries out itself, such as `%const` or `%if`.
+    pub const fn is_preprocessor_directive(&self) -> bool {
+        return match self {
+            TokenType::Constant
             | TokenType::Import
             | TokenType::If
+            | TokenType::Ifdef
+            | TokenType::Ifndef
+            | TokenType::Elif
             | TokenType::Else
             | TokenType::Endif
             | TokenType::Repeat
-            | TokenType::EndRepeat => true,
+            | TokenType::EndRepeat
+            | TokenType::Macro
+            | TokenType::EndMacro
+            | TokenType::ErrorDirective
+            | TokenType::Entry
+            | TokenType::Global
+            | TokenType::Extern => true,
             _ => false,
         };
     }
-}
 
-impl TokenType {
+    /// Whether this is a directive describing the layout of the output, such as `%db` or `%org`.
+    /// These are passed on for assembly and may appear within `%repeat` and `%if` blocks.
+    pub const fn is_data_directive(&self) -> bool {
+        return match self {
+            TokenType::Align
+            | TokenType::DefineByte
+            | TokenType::DefineWord
+            | TokenType::DefineDword
+            | TokenType::DefineQword
+            | TokenType::Ascii
+            | TokenType::Asciiz
+            | TokenType::Fill
+            | TokenType::Org
+            | TokenType::Section => true,
+            _ => false,
+        };
+    }
+
+    /// Whether this token carries no meaning for assembly, such as a comment or line break.
+    ///
+    /// The lexer only produces these when asked to, for tools that reproduce the source.
+    pub const fn is_trivia(&self) -> bool {
+        return match self {
+            TokenType::Comment | TokenType::Newline => true,
+            _ => false,
+        };
+    }
+
+    pub const fn is_register(&self) -> bool {
+        return match self {
+            TokenType::Register(_) => true,
+            _ => false,
+        };
+    }
+
+    pub const fn is_opcode(&self) -> bool {
+        return match self {
+            TokenType::Opcode(_) => true,
+            _ => false,
+        };
+    }
+
+    /// Whether this token can begin an instruction operand.
+    pub const fn is_operand_start(&self) -> bool {
+        return match self {
+            TokenType::Register(_)
+            | TokenType::RegisterRange(..)
+            | TokenType::String(_)
+            | TokenType::Identifier
+            | TokenType::LocalLabel
+            | TokenType::LParen => true,
+            _ => self.is_numeric_literal(),
+        };
+    }
+
     pub fn match_identifier(range: &TextRange) -> Option<TokenType> {
         return Some(match range.string().as_str() {
             "import" => TokenType::Import,
             "const" => TokenType::Constant,
             "if" => TokenType::If,
+            "ifdef" => TokenType::Ifdef,
+            "ifndef" => TokenType::Ifndef,
+            "elif" => TokenType::Elif,
             "else" => TokenType::Else,
             "end_if" => TokenType::Endif,
             "repeat" => TokenType::Repeat,
             "end_repeat" => TokenType::EndRepeat,
+            "macro" => TokenType::Macro,
+            "end_macro" => TokenType::EndMacro,
+            "align" => TokenType::Align,
+            "db" => TokenType::DefineByte,
+            "dw" => TokenType::DefineWord,
+            "dd" => TokenType::DefineDword,
+            "dq" => TokenType::DefineQword,
+            "ascii" => TokenType::Ascii,
+            "asciiz" => TokenType::Asciiz,
+            "fill" => TokenType::Fill,
+            "org" => TokenType::Org,
+            "section" => TokenType::Section,
+            "error" => TokenType::ErrorDirective,
+            "entry" => TokenType::Entry,
+            "global" => TokenType::Global,
+            "extern" => TokenType::Extern,
             _ => return None,
         });
     }
+
+    /// Returns the known directive closest to the given name, if any is close enough to suggest.
+    pub fn closest_directive(name: &str) -> Option<&'static str> {
+        let mut closest = None;
+        let mut closest_distance = MAX_SUGGESTION_DISTANCE + 1;
+
+        for directive in DIRECTIVE_NAMES {
+            let distance = edit_distance(name, directive);
+
+            if distance < closest_distance {
+                closest = Some(*directive);
+                closest_distance = distance;
+            }
+        }
+
+        return closest;
+    }
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        core::mem::swap(&mut previous, &mut current);
+    }
+
+    return previous[b.len()];
+}
+
+/// Finds the token whose range contains the position, such as the token under a cursor.
+///
+/// The tokens must come from a single source and be in source order, as the lexer produces them.
+/// Positions between tokens return None.
+pub fn token_at<'a>(tokens: &'a [Token], pos: &Position) -> Option<&'a Token> {
+    let i = tokens.partition_point(|t| t.lexeme.end() <= *pos);
+
+    return tokens.get(i).filter(|t| t.lexeme.contains(pos));
+}
+
+/// Removes comments, line breaks and other trivia, leaving the tokens significant to assembly.
+pub fn strip_trivia(mut tokens: Vec<Token>) -> Vec<Token> {
+    tokens.retain(|t| !t.is_trivia());
+
+    return tokens;
+}
+
+/// Renders tokens as a JSON array for tooling.
+///
+/// Each token is an object with its `type`, `start` and `end` positions and `lexeme`. Tokens
+/// holding a value also carry `value`, and registers and opcodes carry their symbolic `name`.
+/// Float values that are not finite are given as the strings `"NaN"`, `"inf"` and `"-inf"`.
+pub fn tokens_to_json(tokens: &[Token]) -> String {
+    let mut output = String::from("[");
+
+    for (i, token) in tokens.iter().enumerate() {
+        if i != 0 {
+            output.push(',');
+        }
+
+        let debug = format!("{:?}", token.tp);
+        let name = debug.split('(').next().unwrap();
+        let start = token.lexeme.start();
+        let end = token.lexeme.end();
+
+        write!(
+            output,
+            "{{\"type\":\"{}\",\"start\":{{\"row\":{},\"col\":{}}},\"end\":{{\"row\":{},\"col\":{}}}",
+            name,
+            start.row(),
+            start.col(),
+            end.row(),
+            end.col()
+        )
+        .unwrap();
+
+        match &token.tp {
+            TokenType::Register(r) => write!(
+                output,
+                ",\"value\":{},\"name\":\"{}\"",
+                *r as u8,
+                register_name(*r)
+            ),
+            TokenType::Opcode(code) => write!(
+                output,
+                ",\"value\":{},\"name\":{}",
+                code,
+                json_string(&token.lexeme.string())
+            ),
+            TokenType::UnsignedIntegerLiteral(v) => write!(output, ",\"value\":{}", v),
+            TokenType::SignedIntegerLiteral(v) => write!(output, ",\"value\":{}", v),
+            TokenType::FloatLiteral(v) if v.is_finite() => write!(output, ",\"value\":{}", v),
+            // JSON has no numbers for NaN and the infinities, so these are written as strings
+            TokenType::FloatLiteral(v) => write!(output, ",\"value\":\"{}\"", v),
+            TokenType::String(v) => write!(output, ",\"value\":{}", json_string(v)),
+            TokenType::Operator(op) => write!(output, ",\"name\":\"{:?}\"", op),
+            _ => Ok(()),
+        }
+        .unwrap();
+
+        if let Some(width) = token.width {
+            write!(output, ",\"width\":{}", width).unwrap();
+        }
+
+        write!(
+            output,
+            ",\"lexeme\":{}}}",
+            json_string(&token.lexeme.string())
+        )
+        .unwrap();
+    }
+
+    output.push(']');
+
+    return output;
+}
+
+/// Quotes and escapes a string for use in JSON.
+fn json_string(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+
+    return output;
+}
+
+/// Serializes registers by their numeric code.
+#[cfg(feature = "with-serde")]
+mod register_code {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use vxl_iset::instruction_arguments::Register;
+
+    pub fn serialize<S: Serializer>(register: &Register, serializer: S) -> Result<S::Ok, S::Error> {
+        return (*register as u8).serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Register, D::Error> {
+        let code = u8::deserialize(deserializer)?;
+
+        if code > Register::R9 as u8 {
+            return Err(D::Error::custom("invalid register code"));
+        }
+
+        return Ok(Register::from(code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use either::Either;
+
+    use crate::processing::{Lexer, LexerBuilder};
+    use crate::text_mapping::FileInfoManager;
+
+    use super::*;
+
+    #[test]
+    fn test_token_len() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(String::new(), "halt".to_string());
+        let tokens = Lexer::tokenize(f.clone()).unwrap();
+
+        assert_eq!(tokens[0].len(), 4);
+        assert!(!tokens[0].is_empty());
+
+        let empty = Token::new(
+            TokenType::Eof,
+            TextRange::new(
+                Position::new(4, 0, 4),
+                Position::new(4, 0, 4),
+                Either::Left(f),
+            ),
+        );
+
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_float_literal_eq() {
+        assert_eq!(
+            TokenType::FloatLiteral(f64::NAN),
+            TokenType::FloatLiteral(f64::NAN)
+        );
+        assert_ne!(TokenType::FloatLiteral(0.0), TokenType::FloatLiteral(-0.0));
+        assert_eq!(TokenType::FloatLiteral(-0.0), TokenType::FloatLiteral(-0.0));
+        assert_ne!(
+            TokenType::FloatLiteral(1.0),
+            TokenType::UnsignedIntegerLiteral(1)
+        );
+        assert_ne!(TokenType::Comma, TokenType::Colon);
+        assert_eq!(TokenType::Comma, TokenType::Comma);
+    }
+
+    #[test]
+    fn test_strip_trivia() {
+        let mut f_man = FileInfoManager::new();
+        let f = f_man.new_file(
+            "main.vsm".to_string(),
+            "# header\n\nSTART: /* entry */\n    halt # stop\n".to_string(),
+        );
+
+        let mut lexer = LexerBuilder::new()
+            .keep_comments(true)
+            .emit_newlines(true)
+            .build(Either::Left(f));
+
+        lexer.process().unwrap();
+
+        let tokens = lexer.into_tokens();
+
+        assert!(tokens.iter().any(|t| t.is_comment()));
+        assert!(tokens.iter().any(|t| t.is_newline()));
+
+        assert_eq!(
+            strip_trivia(tokens)
+                .iter()
+                .map(|t| t.token_type())
+                .collect::<Vec<_>>(),
+            vec![
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Opcode(0x45)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokens_to_json() {
+        let tokens = Lexer::tokenize_string("ldi 52, $r0".to_string()).unwrap();
+
+        assert_eq!(
+            tokens_to_json(&tokens),
+            concat!(
+                "[{\"type\":\"Opcode\",\"start\":{\"row\":0,\"col\":0},\"end\":{\"row\":0,\"col\":3},\"value\":3,\"name\":\"ldi\",\"lexeme\":\"ldi\"},",
+                "{\"type\":\"UnsignedIntegerLiteral\",\"start\":{\"row\":0,\"col\":4},\"end\":{\"row\":0,\"col\":6},\"value\":52,\"lexeme\":\"52\"},",
+                "{\"type\":\"Comma\",\"start\":{\"row\":0,\"col\":6},\"end\":{\"row\":0,\"col\":7},\"lexeme\":\",\"},",
+                "{\"type\":\"Register\",\"start\":{\"row\":0,\"col\":9},\"end\":{\"row\":0,\"col\":11},\"value\":6,\"name\":\"r0\",\"lexeme\":\"r0\"}]"
+            )
+        );
+    }
+
+    #[test]
+    fn test_tokens_to_json_non_finite() {
+        let tokens =
+            Lexer::tokenize_string("0f0x7FF8000000000000, 0f0xFFF0000000000000, 0f1.5".to_string())
+                .unwrap();
+        let json = tokens_to_json(&tokens);
+
+        assert!(json.contains("\"value\":\"NaN\""));
+        assert!(json.contains("\"value\":\"-inf\""));
+        assert!(json.contains("\"value\":1.5,"));
+    }
+
+    #[test]
+    fn test_token_at() {
+        let tokens = Lexer::tokenize_string("ldi 52, $r0".to_string()).unwrap();
+
+        assert_eq!(
+            token_at(&tokens, &Position::new(10, 0, 10)),
+            Some(&tokens[3])
+        );
+        assert_eq!(token_at(&tokens, &Position::new(0, 0, 0)), Some(&tokens[0]));
+        assert_eq!(token_at(&tokens, &Position::new(7, 0, 7)), None);
+        assert_eq!(token_at(&tokens, &Position::new(11, 0, 11)), None);
+    }
+
+    #[test]
+    fn test_classification() {
+        assert!(TokenType::UnsignedIntegerLiteral(1).is_numeric_literal());
+        assert!(TokenType::SignedIntegerLiteral(-1).is_numeric_literal());
+        assert!(TokenType::FloatLiteral(1.5).is_numeric_literal());
+        assert!(!TokenType::String("1".into()).is_numeric_literal());
+
+        assert!(TokenType::Repeat.is_directive());
+        assert!(TokenType::Identifier.is_directive());
+        assert!(!TokenType::Opcode(3).is_directive());
+        assert!(!TokenType::Comma.is_directive());
+        assert!(TokenType::DefineByte.is_directive());
+
+        assert!(TokenType::Repeat.is_preprocessor_directive());
+        assert!(!TokenType::DefineByte.is_preprocessor_directive());
+        assert!(!TokenType::Identifier.is_preprocessor_directive());
+        assert!(TokenType::Fill.is_data_directive());
+        assert!(!TokenType::If.is_data_directive());
+
+        assert!(TokenType::Register(Register::R0).is_register());
+        assert!(!TokenType::UnsignedIntegerLiteral(0).is_register());
+
+        assert!(TokenType::Opcode(3).is_opcode());
+        assert!(!TokenType::Identifier.is_opcode());
+
+        assert!(TokenType::Register(Register::R0).is_operand_start());
+        assert!(TokenType::FloatLiteral(0.0).is_operand_start());
+        assert!(TokenType::Identifier.is_operand_start());
+        assert!(TokenType::LParen.is_operand_start());
+        assert!(!TokenType::RParen.is_operand_start());
+        assert!(!TokenType::Comma.is_operand_start());
+        assert!(!TokenType::Opcode(3).is_operand_start());
+    }
+
+    #[test]
+    fn test_closest_directive() {
+        assert_eq!(TokenType::closest_directive("repaet"), Some("repeat"));
+        assert_eq!(TokenType::closest_directive("endif"), Some("end_if"));
+        assert_eq!(TokenType::closest_directive("instruction"), None);
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+    }
 }
@@ -87,16 +87,13 @@ fn main() {
                 }
             }
         } else {
-            (
-                match PreProcessor::new(tokens, flags).run(&root_f) {
-                    Ok(t) => t,
-                    Err(e) => {
-                        report_error(&e);
-                        return;
-                    }
-                },
-                0,
-            )
+            match PreProcessor::new(tokens, flags).run_with_entry(&root_f) {
+                Ok(t) => t,
+                Err(e) => {
+                    report_error(&e);
+                    return;
+                }
+            }
         }
     };
 